@@ -46,11 +46,93 @@ async function main() {
     let isEmulateEnable = false;
   
    
+    //Sound comes out of the emulator as a pile of samples per frame, we queue each pile up right behind the last one
+    //Browsers won't start audio until the user does something, so this gets made on the first rom load
+    let audioCtx = null;
+    let audioNextTime = 0;
+    function play_audio() {
+      const len = emu.get_audio_len();
+      if (audioCtx === null || len == 0) {
+        emu.clear_audio();
+        return;
+      }
+      //Make the view every time, wasm memory can move when it grows
+      const samples = new Float32Array(memory.buffer, emu.get_audio_ptr(), len);
+      const buffer = audioCtx.createBuffer(1, len, emu.get_audio_sample_rate());
+      buffer.copyToChannel(samples, 0);
+      emu.clear_audio();
+      const source = audioCtx.createBufferSource();
+      source.buffer = buffer;
+      source.connect(audioCtx.destination);
+      audioNextTime = Math.max(audioNextTime, audioCtx.currentTime);
+      source.start(audioNextTime);
+      audioNextTime += buffer.duration;
+    }
+
+    //Battery saves go in localStorage under the rom's file name. Written out every few seconds and when the page closes
+    //They can also be pulled out as a .sav file next to the rom and loaded back in, for moving saves between browsers
+    const SAVE_INTERVAL_FRAMES = 300;
+    let saveKey = null;
+    let saveFrameCount = 0;
+    let romName = null;
+    let romBinary = null;
+    function store_save() {
+      if (saveKey === null) return;
+      const data = emu.get_save_data();
+      if (data.length == 0) return;
+      let text = "";
+      for (let i = 0; i < data.length; i++) {
+        text += String.fromCharCode(data[i]);
+      }
+      localStorage.setItem(saveKey, btoa(text));
+    }
+    function restore_save() {
+      const stored = localStorage.getItem(saveKey);
+      if (stored === null) return;
+      const text = atob(stored);
+      const data = new Uint8Array(text.length);
+      for (let i = 0; i < text.length; i++) {
+        data[i] = text.charCodeAt(i);
+      }
+      emu.load_save_data(data);
+    }
+    //game.nes -> game.sav
+    function save_file_name() {
+      const dot = romName.lastIndexOf(".");
+      return (dot > 0 ? romName.substring(0, dot) : romName) + ".sav";
+    }
+    function export_save() {
+      const data = emu.get_save_data();
+      if (data.length == 0) return false;
+      const url = URL.createObjectURL(new Blob([data], { type: "application/octet-stream" }));
+      const link = document.createElement("a");
+      link.href = url;
+      link.download = save_file_name();
+      link.click();
+      URL.revokeObjectURL(url);
+      return true;
+    }
+    //Reload the rom first so nothing from the old save hangs around, flash saves only carry the sectors that changed
+    function import_save(data) {
+      isEmulateEnable = false;
+      emu.load(romBinary);
+      emu.load_save_data(data);
+      store_save();
+      isEmulateEnable = true;
+    }
+    window.addEventListener("beforeunload", store_save);
+
     function emulate_loop() {
       //fun fact: performance.now() is a lot better to use in this context than messing with date stuff.
       const start = performance.now()
       if (isEmulateEnable) {
         emu.step_line();
+        play_audio();
+        saveFrameCount++;
+        if (saveFrameCount >= SAVE_INTERVAL_FRAMES) {
+          saveFrameCount = 0;
+          store_save();
+        }
       }
       const elapsed = (performance.now() - start);
       const diffTime = emulateInterval - elapsed;
@@ -135,6 +217,7 @@ async function main() {
       data: {
         navbarVisible: true,
         loadRomVisible: false,
+        loadSaveVisible: false,
         keyconfigVisible: false,
         gamepadVisible: false,
       },
@@ -147,6 +230,7 @@ async function main() {
             const src = new Uint8Array(arrayBuf);
             sleep(1000);
             isEmulateEnable = false;
+            store_save();
             
             if (!emu.load(src)) {
              
@@ -163,11 +247,44 @@ async function main() {
             });
            
             emu.reset();
+            romName = e.target.files[0].name;
+            romBinary = src;
+            saveKey = "save:" + romName;
+            restore_save();
+            if (audioCtx === null) {
+              audioCtx = new AudioContext();
+            }
             isEmulateEnable = true;
           };
          
           reader.readAsArrayBuffer(e.target.files[0]);
         },
+        saveExport() {
+          if (romName === null || !export_save()) {
+            this.$notify({
+              title: "No Save Data"
+            });
+          }
+        },
+        saveSelect(e) {
+          if (e.target.files.length == 0) return;
+          if (romBinary === null) {
+            this.$notify({
+              title: "Load a ROM first"
+            });
+            return;
+          }
+          const reader = new FileReader();
+          reader.onload = file => {
+            import_save(new Uint8Array(file.target.result));
+            const h = this.$createElement;
+            this.$notify({
+              title: "Load Save Success",
+              message: h("i", { style: "color: teal" }, e.target.files[0].name)
+            });
+          };
+          reader.readAsArrayBuffer(e.target.files[0]);
+        },
         reset() {
          
           if (isEmulateEnable) {
@@ -0,0 +1,51 @@
+/* Audio output, gathers the sound coming off the cartridge into samples the browser can play */
+//The console's own sound channels (the APU) aren't emulated yet, so the only thing making noise right now is expansion audio
+//on the cartridge. Everything gets mixed here once per CPU cycle and averaged down to the output sample rate.
+
+use super::cpu::CPU_FREQ;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+#[derive(Clone, Debug)]
+pub struct AudioOutput {
+    //Finished samples waiting for the browser to come get them
+    pub samples: Vec<f32>,
+    //Running total of the levels since the last sample, we average them so we don't alias quite so badly
+    accumulated_level: f32,
+    accumulated_count: u32,
+    //How far we are towards the next sample, in units of SAMPLE_RATE per CPU cycle
+    sample_phase: u32,
+}
+
+impl Default for AudioOutput {
+    fn default() -> Self {
+        Self {
+            samples: Vec::new(),
+            accumulated_level: 0.0,
+            accumulated_count: 0,
+            sample_phase: 0,
+        }
+    }
+}
+
+impl AudioOutput {
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.accumulated_level = 0.0;
+        self.accumulated_count = 0;
+        self.sample_phase = 0;
+    }
+    //Called once per CPU cycle with everything mixed together
+    pub fn push_level(&mut self, level: f32) {
+        self.accumulated_level += level;
+        self.accumulated_count += 1;
+        self.sample_phase += SAMPLE_RATE;
+        if self.sample_phase >= CPU_FREQ {
+            self.sample_phase -= CPU_FREQ;
+            self.samples
+                .push(self.accumulated_level / (self.accumulated_count as f32));
+            self.accumulated_level = 0.0;
+            self.accumulated_count = 0;
+        }
+    }
+}
@@ -408,9 +408,23 @@ impl Cpu {
         }
     
     }
+    //Run one instruction, then let the rest of the bus know how many cycles it took one cycle at a time.
+    //Cartridge IRQ counters and expansion audio count CPU cycles, so they get ticked from here
+    pub fn step(&mut self, system : &mut System) -> u8{
+        let cycles = self.step_instruction(system);
+        for _ in 0..cycles {
+            system.step_cpu_cycle();
+        }
+        //The cartridge holds the IRQ line low until the game acknowledges it, and the 6502 looks at it between instructions.
+        //interrupt() ignores it while I is set
+        if system.rom.is_irq() {
+            self.interrupt(system, Interrupt::IRQ);
+        }
+        cycles
+    }
     //The meat of the CPU, this function is an OO abomination but without costly abstraction, this is really the easiest way
     //I do not have time to explain every operation here. Or any of them. Look them up. It's neat.
-    pub fn step(&mut self, system : &mut System) -> u8{
+    fn step_instruction(&mut self, system : &mut System) -> u8{
         let inst_pc = self.pc;
         let inst_code = self.fetch8(system);
         
@@ -0,0 +1,127 @@
+/* Action 53, mapper 28 */
+//http://wiki.nesdev.com/w/index.php/Action_53
+//Homebrew multicart board. Each game keeps running on the layout it was written for (NROM, CNROM, UNROM, AOROM and friends)
+//while the menu picks an outer bank and tells the board how big the game is, so the game's own bank writes only move the low bits.
+//$5000-$5FFF selects which of the four registers the next $8000-$FFFF write goes to:
+//  $00: CHR bank, 8k out of 32k of CHR RAM
+//  $01: inner PRG bank, what the game itself writes
+//  $80: mode, MM mirroring, PP PRG layout and SS game size
+//      ..SSPPMM
+//  $81: outer PRG bank in 32k units
+//In the single screen mirroring modes, bit 4 of a $00 or $01 write also picks the screen, which is how AOROM games keep working.
+
+use super::bank::bank_index;
+use super::Mapper;
+use crate::rom::*;
+
+pub const ACTION53_PRG_BANK_SIZE: usize = 0x4000;
+pub const ACTION53_CHR_BANK_SIZE: usize = 0x2000;
+pub const ACTION53_CHR_RAM_SIZE: usize = 0x8000;
+
+#[derive(Debug)]
+pub struct Action53 {
+    data: RomData,
+    //Written at $5000, bits 7 and 0 pick the register
+    reg_select: u8,
+    chr_bank: u8,
+    inner_bank: u8,
+    mode: u8,
+    outer_bank: u8,
+}
+
+impl Action53 {
+    pub fn new(mut data: RomData) -> Self {
+        if data.is_chr_ram() && data.c_ram.len() < ACTION53_CHR_RAM_SIZE {
+            data.c_ram.resize(ACTION53_CHR_RAM_SIZE, 0);
+        }
+        Self {
+            data,
+            reg_select: 0,
+            chr_bank: 0,
+            inner_bank: 0,
+            mode: 0,
+            //The menu lives in the last 32k, which is where this ends up in 32k mode
+            outer_bank: 0xff,
+        }
+    }
+    fn prg_bank(&self, addr: u16) -> usize {
+        let is_upper = addr >= 0xc000;
+        let outer = usize::from(self.outer_bank) << 1;
+        let inner = usize::from(self.inner_bank);
+        //Game size in 16k banks, 32k up to 256k. These low bits come from the inner bank
+        let mask = (2 << ((self.mode >> 4) & 0x03)) - 1;
+        match ((self.mode >> 2) & 0x03, is_upper) {
+            //UNROM with the first half of the outer bank fixed at $8000 (mapper 180 style)
+            (2, false) => outer,
+            (2, true) => (outer & !mask) | (inner & mask),
+            //Plain UNROM, second half of the outer bank fixed at $C000
+            (3, false) => (outer & !mask) | (inner & mask),
+            (3, true) => outer | 0x01,
+            //32k modes, the inner bank counts in 32k so it's one bit further up
+            (_, is_upper) => (outer & !mask) | ((inner << 1) & mask) | usize::from(is_upper),
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        bank_index(self.prg_bank(addr), ACTION53_PRG_BANK_SIZE, addr, self.data.p_rom.len())
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        usize::from(self.chr_bank & 0x03) * ACTION53_CHR_BANK_SIZE + usize::from(addr)
+    }
+    fn write_register(&mut self, data: u8) {
+        match self.reg_select {
+            0x00 | 0x01 => {
+                if self.reg_select == 0x00 {
+                    self.chr_bank = data & 0x03;
+                } else {
+                    self.inner_bank = data & 0x0f;
+                }
+                //Single screen games pick their screen with bit 4, like AOROM
+                if (self.mode & 0x02) == 0x00 {
+                    self.mode = (self.mode & !0x01) | ((data >> 4) & 0x01);
+                }
+            }
+            0x80 => self.mode = data & 0x3f,
+            _ => self.outer_bank = data,
+        }
+    }
+}
+
+impl Mapper for Action53 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            //No RAM and the registers are write only, open bus
+            open_bus(addr)
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        match addr {
+            0x5000..=0x5fff => self.reg_select = data & 0x81,
+            0x8000..=0xffff => self.write_register(data),
+            _ => {}
+        }
+    }
+    //The register select port at $5000
+    fn is_expansion_area(&self) -> bool {
+        true
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.mode & 0x03 {
+            0 => MirrorTable::SingleScreenLower,
+            1 => MirrorTable::SingleScreenUpper,
+            2 => MirrorTable::Vertical,
+            _ => MirrorTable::Horizontal,
+        }
+    }
+}
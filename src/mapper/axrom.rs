@@ -0,0 +1,70 @@
+/* AxROM, mapper 7 */
+//http://wiki.nesdev.com/w/index.php/AxROM
+//32k PRG banks, 8k CHR RAM, and single screen mirroring where the game picks which nametable at runtime.
+//Battletoads and Wizards & Warriors flip between the two screens to scroll.
+
+use super::Mapper;
+use crate::rom::*;
+
+pub const AXROM_PRG_BANK_SIZE: usize = 0x8000;
+
+#[derive(Debug)]
+pub struct Axrom {
+    data: RomData,
+    //Only AMROM has bus conflicts, ANROM and AOROM have a proper register (NES 2.0 submapper 2 asks for them)
+    is_bus_conflict: bool,
+    //Bits 0-3 are the PRG bank, bit 4 is the nametable
+    bank_reg: u8,
+}
+
+impl Axrom {
+    pub fn new(data: RomData) -> Self {
+        let is_bus_conflict = data.submapper_id == 2;
+        Self {
+            data,
+            is_bus_conflict,
+            bank_reg: 0,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let bank = usize::from(self.bank_reg & 0x0f);
+        let offset = usize::from(addr - PRG_ROM_SYSTEM_BASE_ADDR);
+        (bank * AXROM_PRG_BANK_SIZE + offset) % self.data.p_rom.len()
+    }
+}
+
+impl Mapper for Axrom {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+        } else if !is_nondestructive {
+            self.bank_reg = if self.is_bus_conflict {
+                data & self.data.p_rom[self.prg_rom_index(addr)]
+            } else {
+                data
+            };
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(usize::from(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        self.data.write_chr(usize::from(addr), data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        if (self.bank_reg & 0x10) == 0x10 {
+            MirrorTable::SingleScreenUpper
+        } else {
+            MirrorTable::SingleScreenLower
+        }
+    }
+}
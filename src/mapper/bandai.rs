@@ -0,0 +1,214 @@
+/* Bandai FCG and LZ93D50, mappers 16, 153 and 159 */
+//http://wiki.nesdev.com/w/index.php/Bandai_FCG_board
+//Eight 1k CHR banks, a 16k PRG bank at $8000 with the last one fixed at $C000, mirroring control and a 16-bit
+//IRQ counter that counts CPU cycles, all in 16 registers picked by the low 4 address bits.
+//The older FCG-1/2 has its registers at $6000-$7FFF, the LZ93D50 moved them up to $8000-$FFFF and added a serial
+//EEPROM (see eeprom.rs) for saves. Dragon Ball Z, SD Gundam Gaiden, Famicom Jump II.
+//  16: FCG-1/2 for submapper 4, LZ93D50 with a 24C02 for submapper 5. Older dumps don't say so they get both register ranges
+//  159: LZ93D50 with the smaller X24C01
+//  153: LZ93D50 with 8k of battery RAM instead of the EEPROM and an outer 256k PRG bank from the CHR registers. Famicom Jump II
+
+use super::bank::bank_index;
+use super::eeprom::*;
+use super::Mapper;
+use crate::rom::*;
+
+pub const BANDAI_PRG_BANK_SIZE: usize = 0x4000;
+pub const BANDAI_CHR_BANK_SIZE: usize = 0x0400;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BandaiBoard {
+    //Mapper 16 submapper 4
+    Fcg,
+    //Mapper 16 submapper 5 and mapper 159
+    Lz93d50,
+    //Mapper 153
+    Lz93d50Sram,
+    //Mapper 16 submapper 0, could be either so it acts like both
+    Unknown,
+}
+
+#[derive(Debug)]
+pub struct Bandai {
+    data: RomData,
+    board: BandaiBoard,
+    chr_banks: [u8; 8],
+    prg_bank: u8,
+    //153 only, bit 0 of whichever CHR register was written last
+    outer_prg_bank: u8,
+    mirroring: u8,
+    irq_counter: u16,
+    //LZ93D50 only, copied into the counter when the IRQ gets enabled
+    irq_latch: u16,
+    is_irq_enable: bool,
+    is_irq: bool,
+    //153 only, $800D bit 5
+    is_ram_enable: bool,
+    eeprom: Option<Eeprom>,
+}
+
+impl Bandai {
+    pub fn new(data: RomData, board: BandaiBoard, eeprom_chip: Option<EepromChip>) -> Self {
+        Self {
+            data,
+            board,
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            outer_prg_bank: 0,
+            mirroring: 0,
+            irq_counter: 0,
+            irq_latch: 0,
+            is_irq_enable: false,
+            is_irq: false,
+            is_ram_enable: false,
+            eeprom: eeprom_chip.map(Eeprom::new),
+        }
+    }
+    pub fn from_mapper_16(data: RomData) -> Self {
+        match data.submapper_id {
+            4 => Self::new(data, BandaiBoard::Fcg, None),
+            5 => Self::new(data, BandaiBoard::Lz93d50, Some(EepromChip::C24C02)),
+            _ => Self::new(data, BandaiBoard::Unknown, Some(EepromChip::C24C02)),
+        }
+    }
+    fn is_register(&self, addr: u16) -> bool {
+        match self.board {
+            BandaiBoard::Fcg => addr < PRG_ROM_SYSTEM_BASE_ADDR,
+            BandaiBoard::Lz93d50 | BandaiBoard::Lz93d50Sram => addr >= PRG_ROM_SYSTEM_BASE_ADDR,
+            BandaiBoard::Unknown => true,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / BANDAI_PRG_BANK_SIZE;
+        let bank = match (self.board, addr >= 0xc000) {
+            (BandaiBoard::Lz93d50Sram, false) => usize::from((self.outer_prg_bank << 4) | (self.prg_bank & 0x0f)),
+            (BandaiBoard::Lz93d50Sram, true) => usize::from((self.outer_prg_bank << 4) | 0x0f),
+            (_, false) => usize::from(self.prg_bank),
+            (_, true) => num_of_banks.saturating_sub(1),
+        };
+        bank_index(bank, BANDAI_PRG_BANK_SIZE, addr, self.data.p_rom.len())
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        if self.board == BandaiBoard::Lz93d50Sram {
+            //153 has 8k of CHR RAM and no CHR banking, the registers only drive PRG A18
+            return usize::from(addr);
+        }
+        let bank = usize::from(self.chr_banks[usize::from(addr >> 10) & 0x07]);
+        bank * BANDAI_CHR_BANK_SIZE + (usize::from(addr) & (BANDAI_CHR_BANK_SIZE - 1))
+    }
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr & 0x0f {
+            reg @ 0x0..=0x7 => {
+                self.chr_banks[usize::from(reg)] = data;
+                self.outer_prg_bank = data & 0x01;
+            }
+            0x8 => self.prg_bank = data,
+            0x9 => self.mirroring = data & 0x03,
+            0xa => {
+                self.is_irq_enable = (data & 0x01) == 0x01;
+                self.is_irq = false;
+                if self.board != BandaiBoard::Fcg {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            //The FCG writes straight into the counter, the LZ93D50 into a latch. Unknown boards get both
+            0xb => {
+                if self.board != BandaiBoard::Lz93d50 && self.board != BandaiBoard::Lz93d50Sram {
+                    self.irq_counter = (self.irq_counter & 0xff00) | u16::from(data);
+                }
+                self.irq_latch = (self.irq_latch & 0xff00) | u16::from(data);
+            }
+            0xc => {
+                if self.board != BandaiBoard::Lz93d50 && self.board != BandaiBoard::Lz93d50Sram {
+                    self.irq_counter = (self.irq_counter & 0x00ff) | (u16::from(data) << 8);
+                }
+                self.irq_latch = (self.irq_latch & 0x00ff) | (u16::from(data) << 8);
+            }
+            0xd => {
+                //Bit 5 is SCL, bit 6 is SDA. On 153 bit 5 is the RAM enable instead
+                self.is_ram_enable = (data & 0x20) == 0x20;
+                if let Some(eeprom) = &mut self.eeprom {
+                    eeprom.write_lines((data & 0x20) == 0x20, (data & 0x40) == 0x40);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Bandai {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.board == BandaiBoard::Lz93d50Sram {
+                if self.is_ram_enable {
+                    let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                    return self.data.srambytes[index];
+                }
+                return open_bus(addr);
+            }
+            //The EEPROM's data line shows up on bit 4, the rest is open bus
+            match &self.eeprom {
+                Some(eeprom) if eeprom.read_sda() => open_bus(addr) | 0x10,
+                Some(_) => open_bus(addr) & !0x10,
+                None => open_bus(addr),
+            }
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        if self.board == BandaiBoard::Lz93d50Sram && addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_ram_enable {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index] = data;
+            }
+        } else if self.is_register(addr) {
+            self.write_register(addr, data);
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.mirroring {
+            0 => MirrorTable::Vertical,
+            1 => MirrorTable::Horizontal,
+            2 => MirrorTable::SingleScreenLower,
+            _ => MirrorTable::SingleScreenUpper,
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.is_irq
+    }
+    fn step_cpu_cycle(&mut self) {
+        if self.is_irq_enable {
+            if self.irq_counter == 0 {
+                self.is_irq = true;
+            }
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+        }
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        //The EEPROM keeps its contents without a battery, so those always get saved
+        match &self.eeprom {
+            Some(eeprom) => Some(eeprom.data.clone()),
+            None if self.board == BandaiBoard::Lz93d50Sram && self.data.sram => Some(self.data.srambytes.clone()),
+            None => None,
+        }
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        let dst = match &mut self.eeprom {
+            Some(eeprom) => &mut eeprom.data,
+            None => &mut self.data.srambytes,
+        };
+        let len = dst.len().min(data.len());
+        dst[..len].copy_from_slice(&data[..len]);
+    }
+}
@@ -0,0 +1,35 @@
+/* Bank switching pieces shared by the simple latch boards */
+//Most discrete logic boards are a 74-series latch sitting on the CPU bus with its outputs wired to the upper address lines of
+//the ROM chips. All that changes between them is which latch bits go where and how big the banks are.
+
+//Where a bank lands in a chip of the given length. Banks past the end of the chip wrap, like the missing address lines do
+pub fn bank_index(bank: usize, bank_size: usize, addr: u16, len: usize) -> usize {
+    (bank * bank_size + (usize::from(addr) & (bank_size - 1))) % len
+}
+
+//A write only register at $8000-$FFFF
+//http://wiki.nesdev.com/w/index.php/Bus_conflict
+//Boards that don't disable the ROM on writes have it driving the bus at the same time as the CPU. The 0s win,
+//so the latch gets the AND of what was written and the ROM byte at that address.
+#[derive(Copy, Clone, Debug)]
+pub struct BankLatch {
+    pub value: u8,
+    is_bus_conflict: bool,
+}
+
+impl BankLatch {
+    pub fn new(is_bus_conflict: bool) -> Self {
+        Self {
+            value: 0,
+            is_bus_conflict,
+        }
+    }
+    //rom_data is whatever the ROM has at the written address
+    pub fn write(&mut self, data: u8, rom_data: u8) {
+        self.value = if self.is_bus_conflict {
+            data & rom_data
+        } else {
+            data
+        };
+    }
+}
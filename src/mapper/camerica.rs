@@ -0,0 +1,106 @@
+/* Camerica/Codemasters BF909x, mappers 71 and 232 */
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_071
+//BF9093 is UNROM with the register moved to $C000-$FFFF and no bus conflicts. Micro Machines, Bee 52, Dizzy.
+//Fire Hawk's board (BF9097, submapper 1) also has single screen mirroring control at $9000-$9FFF. Old dumps don't say,
+//so a write there is what switches it on.
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_232
+//BF9096 adds an outer 64k block select at $8000-$BFFF for the Quattro multicarts. Submapper 1 is the
+//Aladdin Deck Enhancer, which has the two block bits the wrong way round.
+
+use super::bank::bank_index;
+use super::Mapper;
+use crate::rom::*;
+
+pub const CAMERICA_PRG_BANK_SIZE: usize = 0x4000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CamericaBoard {
+    //Mapper 71
+    Bf9093,
+    //Mapper 232
+    Bf9096,
+}
+
+#[derive(Debug)]
+pub struct Camerica {
+    data: RomData,
+    board: CamericaBoard,
+    prg_bank: u8,
+    //232 only, which 64k block both halves come from
+    outer_bank: u8,
+    //71 only, Some once the game has touched the mirroring register, true for the upper screen
+    single_screen: Option<bool>,
+}
+
+impl Camerica {
+    pub fn new(data: RomData, board: CamericaBoard) -> Self {
+        let single_screen = if board == CamericaBoard::Bf9093 && data.submapper_id == 1 {
+            Some(false)
+        } else {
+            None
+        };
+        Self {
+            data,
+            board,
+            prg_bank: 0,
+            outer_bank: 0,
+            single_screen,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / CAMERICA_PRG_BANK_SIZE;
+        let is_fixed = addr >= 0xc000;
+        let bank = match (self.board, is_fixed) {
+            (CamericaBoard::Bf9093, false) => usize::from(self.prg_bank),
+            (CamericaBoard::Bf9093, true) => num_of_banks.saturating_sub(1),
+            //Each 64k block is laid out like its own UNROM, last bank of the block fixed at $C000
+            (CamericaBoard::Bf9096, false) => usize::from((self.outer_bank << 2) | (self.prg_bank & 0x03)),
+            (CamericaBoard::Bf9096, true) => usize::from((self.outer_bank << 2) | 0x03),
+        };
+        bank_index(bank, CAMERICA_PRG_BANK_SIZE, addr, self.data.p_rom.len())
+    }
+}
+
+impl Mapper for Camerica {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+        } else if !is_nondestructive {
+            match (self.board, addr) {
+                (CamericaBoard::Bf9093, 0x9000..=0x9fff) => self.single_screen = Some((data & 0x10) == 0x10),
+                (CamericaBoard::Bf9093, 0xc000..=0xffff) => self.prg_bank = data & 0x0f,
+                (CamericaBoard::Bf9096, 0x8000..=0xbfff) => {
+                    self.outer_bank = if self.data.submapper_id == 1 {
+                        ((data >> 4) & 0x01) | ((data >> 2) & 0x02)
+                    } else {
+                        (data >> 3) & 0x03
+                    };
+                }
+                (CamericaBoard::Bf9096, _) => self.prg_bank = data & 0x03,
+                _ => {}
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(usize::from(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        self.data.write_chr(usize::from(addr), data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.single_screen {
+            Some(true) => MirrorTable::SingleScreenUpper,
+            Some(false) => MirrorTable::SingleScreenLower,
+            None => self.data.header_mirror_table(),
+        }
+    }
+}
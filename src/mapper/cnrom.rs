@@ -0,0 +1,96 @@
+/* CNROM, mappers 3 and 185 */
+//http://wiki.nesdev.com/w/index.php/CNROM
+//Fixed 16k or 32k of PRG and switchable 8k CHR ROM banks. The register is a plain latch, so writes have bus conflicts like UxROM.
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_185
+//Mapper 185 is CNROM with a diode or two on the CHR chip enable instead of bank lines. The wrong latch value shuts the CHR ROM
+//off entirely, and a handful of games (Mighty Bomb Jack, Spy vs Spy JP) write junk there and check they get garbage back
+//as copy protection.
+
+use super::Mapper;
+use crate::rom::*;
+
+pub const CNROM_CHR_BANK_SIZE: usize = 0x2000;
+
+#[derive(Copy, Clone, Debug)]
+pub enum CnromBoard {
+    //Mapper 3
+    Cnrom,
+    //Mapper 185, the NES 2.0 submapper says which latch value turns the CHR ROM on
+    ChrDisable { submapper_id: u8 },
+}
+
+#[derive(Debug)]
+pub struct Cnrom {
+    data: RomData,
+    board: CnromBoard,
+    chr_bank: u8,
+}
+
+impl Cnrom {
+    pub fn new(data: RomData, board: CnromBoard) -> Self {
+        Self {
+            data,
+            board,
+            chr_bank: 0,
+        }
+    }
+    fn is_chr_enable(&self) -> bool {
+        match self.board {
+            CnromBoard::Cnrom => true,
+            CnromBoard::ChrDisable { submapper_id } => {
+                if (4..=7).contains(&submapper_id) {
+                    //Submappers 4-7 spell out the enabling value in the low two bits
+                    (self.chr_bank & 0x03) == submapper_id - 4
+                } else {
+                    //Old dumps don't say, but every game turns the ROM off with either 0 in the low nibble or $13
+                    (self.chr_bank & 0x0f) != 0 && self.chr_bank != 0x13
+                }
+            }
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        usize::from(addr - PRG_ROM_SYSTEM_BASE_ADDR) % self.data.p_rom.len()
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = match self.board {
+            CnromBoard::Cnrom => usize::from(self.chr_bank),
+            CnromBoard::ChrDisable { .. } => 0,
+        };
+        bank * CNROM_CHR_BANK_SIZE + usize::from(addr)
+    }
+}
+
+impl Mapper for Cnrom {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+        } else if !is_nondestructive {
+            //Bus conflict, the latch gets whatever the ROM and the CPU agree on
+            self.chr_bank = data & self.data.p_rom[self.prg_rom_index(addr)];
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        if self.is_chr_enable() {
+            self.data.read_chr(self.chr_index(addr))
+        } else {
+            //Nothing drives the PPU bus, the pull-ups win
+            0xff
+        }
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        self.data.header_mirror_table()
+    }
+}
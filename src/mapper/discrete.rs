@@ -0,0 +1,143 @@
+/* Discrete logic boards, mappers 11, 13, 34 and 66 */
+//A single latch picking 32k PRG banks and/or CHR banks, each board just wires its bits differently.
+//http://wiki.nesdev.com/w/index.php/GxROM
+//  66: bits 4-5 pick 32k of PRG, bits 0-1 pick 8k of CHR ROM. SMB + Duck Hunt
+//http://wiki.nesdev.com/w/index.php/Color_Dreams
+//  11: the other way round, bits 0-1 for PRG and bits 4-7 for CHR. Unlicensed Color Dreams and Wisdom Tree games
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_034
+//  34: two unrelated boards share this number. BNROM (Deadly Towers) is a 32k PRG latch with CHR RAM,
+//      AVE NINA-001 (Impossible Mission II) has registers at $7FFD-$7FFF for PRG and two 4k CHR ROM banks, and no bus conflicts.
+//      Submapper 1 is NINA-001 and 2 is BNROM, without one anything with more than 8k of CHR ROM is NINA-001
+//http://wiki.nesdev.com/w/index.php/CPROM
+//  13: fixed PRG and 16k CHR RAM, bits 0-1 pick the 4k at $1000. Videomation
+
+use super::bank::{bank_index, BankLatch};
+use super::Mapper;
+use crate::rom::*;
+
+pub const DISCRETE_PRG_BANK_SIZE: usize = 0x8000;
+pub const NINA001_CHR_BANK_SIZE: usize = 0x1000;
+pub const CPROM_CHR_BANK_SIZE: usize = 0x1000;
+pub const CPROM_CHR_RAM_SIZE: usize = 0x4000;
+pub const NINA001_PRG_REG_ADDR: u16 = 0x7ffd;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DiscreteBoard {
+    Gxrom,
+    ColorDreams,
+    Bnrom,
+    Nina001,
+    Cprom,
+}
+
+impl DiscreteBoard {
+    //Mapper 34 gets worked out from the submapper or the CHR size
+    pub fn from_mapper_34(data: &RomData) -> Self {
+        match data.submapper_id {
+            1 => DiscreteBoard::Nina001,
+            2 => DiscreteBoard::Bnrom,
+            _ if data.c_rom.len() > CHR_ROM_BANK_SIZE => DiscreteBoard::Nina001,
+            _ => DiscreteBoard::Bnrom,
+        }
+    }
+    fn chr_bank_size(self) -> usize {
+        match self {
+            DiscreteBoard::Nina001 => NINA001_CHR_BANK_SIZE,
+            DiscreteBoard::Cprom => CPROM_CHR_BANK_SIZE,
+            _ => CHR_ROM_BANK_SIZE,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Discrete {
+    data: RomData,
+    board: DiscreteBoard,
+    latch: BankLatch,
+    prg_bank: usize,
+    //One bank per slot, boards with 8k CHR banks only use the first
+    chr_banks: [usize; 2],
+}
+
+impl Discrete {
+    pub fn new(mut data: RomData, board: DiscreteBoard) -> Self {
+        //CPROM always has 16k of CHR RAM, old headers ask for 8k
+        if board == DiscreteBoard::Cprom && data.is_chr_ram() && data.c_ram.len() < CPROM_CHR_RAM_SIZE {
+            data.c_ram.resize(CPROM_CHR_RAM_SIZE, 0);
+        }
+        //All of them have bus conflicts except NINA-001, which has real registers
+        let latch = BankLatch::new(board != DiscreteBoard::Nina001);
+        Self {
+            data,
+            board,
+            latch,
+            prg_bank: 0,
+            chr_banks: [0; 2],
+        }
+    }
+    //Works the banks out from a latch write
+    fn update_banks(&mut self) {
+        let value = usize::from(self.latch.value);
+        match self.board {
+            DiscreteBoard::Gxrom => {
+                self.prg_bank = (value >> 4) & 0x03;
+                self.chr_banks[0] = value & 0x03;
+            }
+            DiscreteBoard::ColorDreams => {
+                self.prg_bank = value & 0x03;
+                self.chr_banks[0] = value >> 4;
+            }
+            DiscreteBoard::Bnrom => self.prg_bank = value,
+            DiscreteBoard::Cprom => self.chr_banks[1] = value & 0x03,
+            DiscreteBoard::Nina001 => {}
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        bank_index(self.prg_bank, DISCRETE_PRG_BANK_SIZE, addr, self.data.p_rom.len())
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank_size = self.board.chr_bank_size();
+        let slot = usize::from(addr) / bank_size;
+        let bank = self.chr_banks[slot];
+        bank * bank_size + (usize::from(addr) & (bank_size - 1))
+    }
+}
+
+impl Mapper for Discrete {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+            //NINA-001's registers sit on top of the RAM, the write goes to both
+            if self.board == DiscreteBoard::Nina001 && !is_nondestructive && addr >= NINA001_PRG_REG_ADDR {
+                match addr {
+                    0x7ffd => self.prg_bank = usize::from(data & 0x01),
+                    0x7ffe => self.chr_banks[0] = usize::from(data & 0x0f),
+                    _ => self.chr_banks[1] = usize::from(data & 0x0f),
+                }
+            }
+        } else if !is_nondestructive && self.board != DiscreteBoard::Nina001 {
+            let rom_data = self.data.p_rom[self.prg_rom_index(addr)];
+            self.latch.write(data, rom_data);
+            self.update_banks();
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        self.data.header_mirror_table()
+    }
+}
@@ -0,0 +1,393 @@
+/* 24C01/24C02 serial EEPROM */
+//http://wiki.nesdev.com/w/index.php/Bandai_FCG_board#Serial_EEPROM
+//Bandai's boards save to a little I2C EEPROM instead of battery RAM. The game bit-bangs the clock (SCL) and data (SDA)
+//lines through a mapper register and reads SDA back, so all we see is the two line levels changing.
+//The 24C02 is a normal I2C part, a device address byte (1010xxx + R/W), a word address byte, then data MSB first.
+//The older X24C01 skips the device address, the first byte is the 7 bit word address and R/W, and it sends everything LSB first.
+//Both ack each byte they receive by pulling SDA low on the 9th clock, and the master does the same on reads to ask for another byte.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EepromChip {
+    //128 bytes, mapper 159 and some mapper 16
+    X24C01,
+    //256 bytes, mapper 16
+    C24C02,
+}
+
+impl EepromChip {
+    pub fn size(&self) -> usize {
+        match self {
+            EepromChip::X24C01 => 0x80,
+            EepromChip::C24C02 => 0x100,
+        }
+    }
+    //Writes wrap round inside a page instead of running on into the next one
+    fn page_size(&self) -> u8 {
+        match self {
+            EepromChip::X24C01 => 4,
+            EepromChip::C24C02 => 8,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum EepromMode {
+    //Waiting for a start condition
+    Idle,
+    //24C02 only, the 1010xxx + R/W byte
+    DeviceAddress,
+    //The word address. On the X24C01 this byte carries R/W too
+    WordAddress,
+    //Master is sending data bytes to be stored
+    Write,
+    //We're sending data bytes to the master
+    Read,
+}
+
+#[derive(Debug)]
+pub struct Eeprom {
+    pub chip: EepromChip,
+    pub data: Vec<u8>,
+    mode: EepromMode,
+    //Where to go once the current byte and its ack are done
+    next_mode: EepromMode,
+    //Clocks into the current byte, 8 data bits and then the ack on the 9th
+    bit: u8,
+    shift: u8,
+    address: u8,
+    //Whether we're pulling SDA low to ack the byte just received
+    is_ack: bool,
+    //Line levels as of the last write
+    scl: bool,
+    sda: bool,
+    //What we're driving onto SDA, true is released. The line is wired-AND so either side can pull it low
+    output: bool,
+}
+
+impl Eeprom {
+    pub fn new(chip: EepromChip) -> Self {
+        Self {
+            chip,
+            data: vec![0xff; chip.size()],
+            mode: EepromMode::Idle,
+            next_mode: EepromMode::Idle,
+            bit: 0,
+            shift: 0,
+            address: 0,
+            is_ack: false,
+            scl: false,
+            sda: false,
+            output: true,
+        }
+    }
+    //The SDA level the game reads back
+    pub fn read_sda(&self) -> bool {
+        self.output && self.sda
+    }
+    //The game has set the two lines, work out what edge (if any) that was
+    pub fn write_lines(&mut self, scl: bool, sda: bool) {
+        let (prev_scl, prev_sda) = (self.scl, self.sda);
+        self.scl = scl;
+        self.sda = sda;
+
+        if prev_scl && scl {
+            //SDA is only meant to move while SCL is low, if it changes while SCL is high that's a start or a stop
+            if prev_sda && !sda {
+                self.start();
+            } else if !prev_sda && sda {
+                self.stop();
+            }
+        } else if !prev_scl && scl {
+            self.rising_edge();
+        } else if prev_scl && !scl {
+            self.falling_edge();
+        }
+    }
+    fn is_lsb_first(&self) -> bool {
+        self.chip == EepromChip::X24C01
+    }
+    fn start(&mut self) {
+        self.mode = match self.chip {
+            EepromChip::X24C01 => EepromMode::WordAddress,
+            EepromChip::C24C02 => EepromMode::DeviceAddress,
+        };
+        self.bit = 0;
+        self.shift = 0;
+        self.is_ack = false;
+        self.output = true;
+    }
+    fn stop(&mut self) {
+        self.mode = EepromMode::Idle;
+        self.bit = 0;
+        self.is_ack = false;
+        self.output = true;
+    }
+    //The master samples SDA while SCL is high, so this is where bits come in
+    fn rising_edge(&mut self) {
+        match self.mode {
+            EepromMode::Idle => {}
+            EepromMode::Read => {
+                if self.bit == 8 {
+                    //The master's ack, low asks for the next byte and high means it's done
+                    self.next_mode = if self.sda {
+                        EepromMode::Idle
+                    } else {
+                        self.address = self.address.wrapping_add(1);
+                        EepromMode::Read
+                    };
+                }
+                self.bit += 1;
+            }
+            _ => {
+                if self.bit < 8 {
+                    let bit = u8::from(self.sda);
+                    self.shift = if self.is_lsb_first() {
+                        (self.shift >> 1) | (bit << 7)
+                    } else {
+                        (self.shift << 1) | bit
+                    };
+                }
+                self.bit += 1;
+                if self.bit == 8 {
+                    self.receive_byte();
+                }
+            }
+        }
+    }
+    //And we change what we drive on SDA while SCL is low
+    fn falling_edge(&mut self) {
+        match self.bit {
+            //Byte done, the next one starts here
+            9 => {
+                self.bit = 0;
+                self.mode = self.next_mode;
+                self.output = true;
+                if self.mode == EepromMode::Read {
+                    self.load_read_byte();
+                }
+            }
+            //Ack slot for whoever is receiving
+            8 => {
+                self.output = if self.mode == EepromMode::Read { true } else { !self.is_ack };
+            }
+            bit if self.mode == EepromMode::Read => {
+                self.output = self.read_bit(bit);
+            }
+            _ => {}
+        }
+    }
+    fn receive_byte(&mut self) {
+        let mask = (self.chip.size() - 1) as u8;
+        self.is_ack = true;
+        self.next_mode = match self.mode {
+            EepromMode::DeviceAddress => {
+                if (self.shift & 0xf0) == 0xa0 {
+                    if (self.shift & 0x01) == 0x01 {
+                        EepromMode::Read
+                    } else {
+                        EepromMode::WordAddress
+                    }
+                } else {
+                    //Not for us, keep quiet until the next start
+                    self.is_ack = false;
+                    EepromMode::Idle
+                }
+            }
+            EepromMode::WordAddress => {
+                self.address = self.shift & mask;
+                //Only the X24C01 has R/W here, it comes in last so it lands in bit 7
+                if self.chip == EepromChip::X24C01 && (self.shift & 0x80) == 0x80 {
+                    EepromMode::Read
+                } else {
+                    EepromMode::Write
+                }
+            }
+            EepromMode::Write => {
+                self.data[usize::from(self.address & mask)] = self.shift;
+                let page_mask = self.chip.page_size() - 1;
+                self.address = (self.address & !page_mask) | (self.address.wrapping_add(1) & page_mask);
+                EepromMode::Write
+            }
+            _ => EepromMode::Idle,
+        };
+    }
+    //Sets up a byte to send, and puts its first bit on the line straight away
+    fn load_read_byte(&mut self) {
+        let mask = (self.chip.size() - 1) as u8;
+        self.shift = self.data[usize::from(self.address & mask)];
+        self.output = self.read_bit(0);
+    }
+    fn read_bit(&self, bit: u8) -> bool {
+        let shift = if self.is_lsb_first() { bit } else { 7 - bit };
+        ((self.shift >> shift) & 0x01) == 0x01
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Plays the master's side of the bus, leaving SCL low between steps like a game would
+    fn start(eeprom: &mut Eeprom) {
+        eeprom.write_lines(false, true);
+        eeprom.write_lines(true, true);
+        eeprom.write_lines(true, false);
+        eeprom.write_lines(false, false);
+    }
+    fn stop(eeprom: &mut Eeprom) {
+        eeprom.write_lines(false, false);
+        eeprom.write_lines(true, false);
+        eeprom.write_lines(true, true);
+    }
+    fn send_bit(eeprom: &mut Eeprom, bit: bool) {
+        eeprom.write_lines(false, bit);
+        eeprom.write_lines(true, bit);
+        eeprom.write_lines(false, bit);
+    }
+    //Returns whether the chip acked
+    fn send_byte(eeprom: &mut Eeprom, data: u8) -> bool {
+        for i in 0..8 {
+            let shift = if eeprom.is_lsb_first() { i } else { 7 - i };
+            send_bit(eeprom, ((data >> shift) & 0x01) == 0x01);
+        }
+        eeprom.write_lines(false, true);
+        eeprom.write_lines(true, true);
+        let is_ack = !eeprom.read_sda();
+        eeprom.write_lines(false, true);
+        is_ack
+    }
+    fn receive_byte(eeprom: &mut Eeprom, is_ack: bool) -> u8 {
+        let mut data = 0;
+        for i in 0..8 {
+            eeprom.write_lines(false, true);
+            eeprom.write_lines(true, true);
+            let shift = if eeprom.is_lsb_first() { i } else { 7 - i };
+            data |= u8::from(eeprom.read_sda()) << shift;
+            eeprom.write_lines(false, true);
+        }
+        send_bit(eeprom, !is_ack);
+        data
+    }
+
+    #[test]
+    fn c24c02_write() {
+        let mut eeprom = Eeprom::new(EepromChip::C24C02);
+        start(&mut eeprom);
+        assert!(send_byte(&mut eeprom, 0xa0));
+        assert!(send_byte(&mut eeprom, 0x16));
+        assert!(send_byte(&mut eeprom, 0x12));
+        assert!(send_byte(&mut eeprom, 0x34));
+        //Runs off the end of the 8 byte page and wraps back to its start
+        assert!(send_byte(&mut eeprom, 0x56));
+        stop(&mut eeprom);
+        assert_eq!(&eeprom.data[0x16..0x18], &[0x12, 0x34]);
+        assert_eq!(eeprom.data[0x10], 0x56);
+        assert_eq!(eeprom.data[0x18], 0xff);
+        assert_eq!(eeprom.mode, EepromMode::Idle);
+    }
+
+    #[test]
+    fn c24c02_other_device_address() {
+        let mut eeprom = Eeprom::new(EepromChip::C24C02);
+        start(&mut eeprom);
+        assert!(!send_byte(&mut eeprom, 0x50));
+        assert!(!send_byte(&mut eeprom, 0x00));
+        stop(&mut eeprom);
+        assert!(eeprom.data.iter().all(|&data| data == 0xff));
+    }
+
+    #[test]
+    fn c24c02_sequential_read() {
+        let mut eeprom = Eeprom::new(EepromChip::C24C02);
+        eeprom.data[0x40..0x43].copy_from_slice(&[0x12, 0x34, 0x56]);
+        //Set the address with a write, then a repeated start to turn it round
+        start(&mut eeprom);
+        assert!(send_byte(&mut eeprom, 0xa0));
+        assert!(send_byte(&mut eeprom, 0x40));
+        start(&mut eeprom);
+        assert!(send_byte(&mut eeprom, 0xa1));
+        assert_eq!(receive_byte(&mut eeprom, true), 0x12);
+        assert_eq!(receive_byte(&mut eeprom, true), 0x34);
+        assert_eq!(receive_byte(&mut eeprom, false), 0x56);
+        //After the nack the chip lets go of the line
+        assert_eq!(eeprom.mode, EepromMode::Idle);
+        assert!(eeprom.read_sda());
+        stop(&mut eeprom);
+        assert_eq!(eeprom.mode, EepromMode::Idle);
+    }
+
+    #[test]
+    fn c24c02_aborted_write() {
+        let mut eeprom = Eeprom::new(EepromChip::C24C02);
+        start(&mut eeprom);
+        assert!(send_byte(&mut eeprom, 0xa0));
+        assert!(send_byte(&mut eeprom, 0x20));
+        for _ in 0..4 {
+            send_bit(&mut eeprom, false);
+        }
+        stop(&mut eeprom);
+        assert_eq!(eeprom.data[0x20], 0xff);
+        assert_eq!(eeprom.mode, EepromMode::Idle);
+        assert!(eeprom.read_sda());
+        //And the next transfer starts clean
+        start(&mut eeprom);
+        assert!(send_byte(&mut eeprom, 0xa0));
+        assert!(send_byte(&mut eeprom, 0x20));
+        assert!(send_byte(&mut eeprom, 0x99));
+        stop(&mut eeprom);
+        assert_eq!(eeprom.data[0x20], 0x99);
+    }
+
+    #[test]
+    fn x24c01_write() {
+        let mut eeprom = Eeprom::new(EepromChip::X24C01);
+        start(&mut eeprom);
+        //No device address, straight to the word address with R/W clear
+        assert!(send_byte(&mut eeprom, 0x05));
+        assert!(send_byte(&mut eeprom, 0xab));
+        assert!(send_byte(&mut eeprom, 0xcd));
+        assert!(send_byte(&mut eeprom, 0xef));
+        //4 byte pages, so this lands back at $04
+        assert!(send_byte(&mut eeprom, 0x01));
+        stop(&mut eeprom);
+        assert_eq!(&eeprom.data[0x04..0x08], &[0x01, 0xab, 0xcd, 0xef]);
+        assert_eq!(eeprom.mode, EepromMode::Idle);
+    }
+
+    #[test]
+    fn x24c01_sequential_read() {
+        let mut eeprom = Eeprom::new(EepromChip::X24C01);
+        eeprom.data[0x7e] = 0x81;
+        eeprom.data[0x7f] = 0x42;
+        eeprom.data[0x00] = 0x24;
+        start(&mut eeprom);
+        assert!(send_byte(&mut eeprom, 0x10));
+        start(&mut eeprom);
+        assert!(send_byte(&mut eeprom, 0x80 | 0x7e));
+        assert_eq!(receive_byte(&mut eeprom, true), 0x81);
+        assert_eq!(receive_byte(&mut eeprom, true), 0x42);
+        //Reads aren't stuck in a page, they run round the whole chip
+        assert_eq!(receive_byte(&mut eeprom, false), 0x24);
+        assert_eq!(eeprom.mode, EepromMode::Idle);
+        stop(&mut eeprom);
+        assert_eq!(eeprom.mode, EepromMode::Idle);
+    }
+
+    #[test]
+    fn x24c01_aborted_write() {
+        let mut eeprom = Eeprom::new(EepromChip::X24C01);
+        start(&mut eeprom);
+        assert!(send_byte(&mut eeprom, 0x30));
+        for _ in 0..5 {
+            send_bit(&mut eeprom, false);
+        }
+        stop(&mut eeprom);
+        assert_eq!(eeprom.data[0x30], 0xff);
+        assert_eq!(eeprom.mode, EepromMode::Idle);
+        start(&mut eeprom);
+        assert!(send_byte(&mut eeprom, 0x30));
+        assert!(send_byte(&mut eeprom, 0x5a));
+        stop(&mut eeprom);
+        assert_eq!(eeprom.data[0x30], 0x5a);
+    }
+}
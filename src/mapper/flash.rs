@@ -0,0 +1,267 @@
+/* SST39SF040 flash */
+//http://wiki.nesdev.com/w/index.php/UNROM_512#Flash_ROM_Programming
+//Some homebrew boards use flash for PRG so the game can save by rewriting its own ROM. Writes normally do nothing,
+//but the chip watches for unlock sequences ($AA to $5555, $55 to $2AAA, then a command to $5555) and reacts to those:
+//  $A0: the next write programs one byte. Programming can only clear bits, getting 1s back needs an erase
+//  $80: erase, which takes a second unlock and then $30 to a sector address (4k) or $10 to $5555 (whole chip)
+//  $90: software ID, reads return the manufacturer and device IDs until $F0 is written
+//Real programs and erases take a little while and the chip reports progress on the data lines, we just finish straight away.
+
+pub const FLASH_SECTOR_SIZE: usize = 0x1000;
+pub const FLASH_MANUFACTURER_ID: u8 = 0xbf;
+pub const FLASH_DEVICE_ID: u8 = 0xb7;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FlashState {
+    Ready,
+    //Seen $AA to $5555
+    Unlock1,
+    //Seen $55 to $2AAA, the next write to $5555 is a command
+    Unlock2,
+    //The next write is the byte to program
+    Program,
+    //Got $80, now waiting on the second unlock
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+}
+
+#[derive(Debug)]
+pub struct Flash {
+    state: FlashState,
+    is_id_mode: bool,
+    //Which sectors have been written since the ROM was loaded, only those need saving
+    dirty_sectors: Vec<bool>,
+}
+
+impl Flash {
+    pub fn new(len: usize) -> Self {
+        Self {
+            state: FlashState::Ready,
+            is_id_mode: false,
+            dirty_sectors: vec![false; len.div_ceil(FLASH_SECTOR_SIZE)],
+        }
+    }
+    //index is the offset into the chip. In ID mode the chip answers instead of the array
+    pub fn read(&self, rom: &[u8], index: usize) -> u8 {
+        if self.is_id_mode {
+            if (index & 0x01) == 0x00 {
+                FLASH_MANUFACTURER_ID
+            } else {
+                FLASH_DEVICE_ID
+            }
+        } else {
+            rom[index % rom.len()]
+        }
+    }
+    pub fn write(&mut self, rom: &mut [u8], index: usize, data: u8) {
+        let index = index % rom.len();
+        //Only the low 15 address bits are decoded for the command addresses
+        let command_addr = index & 0x7fff;
+        self.state = match (self.state, command_addr, data) {
+            (FlashState::Program, _, _) => {
+                rom[index] &= data;
+                self.dirty_sectors[index / FLASH_SECTOR_SIZE] = true;
+                FlashState::Ready
+            }
+            //Otherwise $F0 anywhere backs out of whatever was going on
+            (_, _, 0xf0) => {
+                self.is_id_mode = false;
+                FlashState::Ready
+            }
+            (FlashState::Ready, 0x5555, 0xaa) => FlashState::Unlock1,
+            (FlashState::Unlock1, 0x2aaa, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, 0x5555, 0xa0) => FlashState::Program,
+            (FlashState::Unlock2, 0x5555, 0x80) => FlashState::Erase,
+            (FlashState::Unlock2, 0x5555, 0x90) => {
+                self.is_id_mode = true;
+                FlashState::Ready
+            }
+            (FlashState::Erase, 0x5555, 0xaa) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, 0x2aaa, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, _, 0x30) => {
+                let base = index & !(FLASH_SECTOR_SIZE - 1);
+                let end = (base + FLASH_SECTOR_SIZE).min(rom.len());
+                rom[base..end].fill(0xff);
+                self.dirty_sectors[index / FLASH_SECTOR_SIZE] = true;
+                FlashState::Ready
+            }
+            (FlashState::EraseUnlock2, 0x5555, 0x10) => {
+                rom.fill(0xff);
+                self.dirty_sectors.fill(true);
+                FlashState::Ready
+            }
+            //Anything out of sequence drops back to the start
+            _ => FlashState::Ready,
+        };
+    }
+    //Each written sector as a little endian u16 sector number followed by the sector itself
+    pub fn save_data(&self, rom: &[u8]) -> Vec<u8> {
+        let mut data = vec![];
+        for (sector, _) in self.dirty_sectors.iter().enumerate().filter(|(_, is_dirty)| **is_dirty) {
+            let base = sector * FLASH_SECTOR_SIZE;
+            let end = (base + FLASH_SECTOR_SIZE).min(rom.len());
+            data.extend_from_slice(&(sector as u16).to_le_bytes());
+            data.extend_from_slice(&rom[base..end]);
+        }
+        data
+    }
+    pub fn load_save_data(&mut self, rom: &mut [u8], data: &[u8]) {
+        for chunk in data.chunks(2 + FLASH_SECTOR_SIZE) {
+            if chunk.len() < 2 {
+                break;
+            }
+            let sector = usize::from(u16::from_le_bytes([chunk[0], chunk[1]]));
+            let base = sector * FLASH_SECTOR_SIZE;
+            if base >= rom.len() {
+                continue;
+            }
+            let len = (chunk.len() - 2).min(rom.len() - base);
+            rom[base..base + len].copy_from_slice(&chunk[2..2 + len]);
+            //Still different from the ROM file, so it has to go out again on the next save
+            self.dirty_sectors[sector] = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM_SIZE: usize = 0x10000;
+
+    fn command(flash: &mut Flash, rom: &mut [u8], command: u8) {
+        flash.write(rom, 0x5555, 0xaa);
+        flash.write(rom, 0x2aaa, 0x55);
+        flash.write(rom, 0x5555, command);
+    }
+    fn program(flash: &mut Flash, rom: &mut [u8], index: usize, data: u8) {
+        command(flash, rom, 0xa0);
+        flash.write(rom, index, data);
+    }
+    fn erase(flash: &mut Flash, rom: &mut [u8], index: usize, data: u8) {
+        command(flash, rom, 0x80);
+        flash.write(rom, 0x5555, 0xaa);
+        flash.write(rom, 0x2aaa, 0x55);
+        flash.write(rom, index, data);
+    }
+
+    #[test]
+    fn writes_without_unlock_do_nothing() {
+        let mut rom = vec![0xff; ROM_SIZE];
+        let mut flash = Flash::new(ROM_SIZE);
+        flash.write(&mut rom, 0x1234, 0x00);
+        //Wrong address for the second unlock byte
+        flash.write(&mut rom, 0x5555, 0xaa);
+        flash.write(&mut rom, 0x2aab, 0x55);
+        flash.write(&mut rom, 0x5555, 0xa0);
+        flash.write(&mut rom, 0x1234, 0x00);
+        assert!(rom.iter().all(|&data| data == 0xff));
+        assert!(flash.save_data(&rom).is_empty());
+    }
+
+    #[test]
+    fn byte_program() {
+        let mut rom = vec![0xff; ROM_SIZE];
+        let mut flash = Flash::new(ROM_SIZE);
+        program(&mut flash, &mut rom, 0x1234, 0x5a);
+        assert_eq!(flash.read(&rom, 0x1234), 0x5a);
+        //Programming can only clear bits
+        program(&mut flash, &mut rom, 0x1234, 0xf0);
+        assert_eq!(rom[0x1234], 0x50);
+        //And it's one byte per unlock
+        flash.write(&mut rom, 0x1235, 0x00);
+        assert_eq!(rom[0x1235], 0xff);
+        //Command addresses only look at the low 15 bits
+        flash.write(&mut rom, 0xd555, 0xaa);
+        flash.write(&mut rom, 0xaaaa, 0x55);
+        flash.write(&mut rom, 0xd555, 0xa0);
+        flash.write(&mut rom, 0x8000, 0x12);
+        assert_eq!(rom[0x8000], 0x12);
+    }
+
+    #[test]
+    fn sector_erase() {
+        let mut rom = vec![0x00; ROM_SIZE];
+        let mut flash = Flash::new(ROM_SIZE);
+        erase(&mut flash, &mut rom, 0x3456, 0x30);
+        assert_eq!(rom[0x2fff], 0x00);
+        assert!(rom[0x3000..0x4000].iter().all(|&data| data == 0xff));
+        assert_eq!(rom[0x4000], 0x00);
+    }
+
+    #[test]
+    fn chip_erase() {
+        let mut rom = vec![0x00; ROM_SIZE];
+        let mut flash = Flash::new(ROM_SIZE);
+        //$10 only counts at $5555
+        erase(&mut flash, &mut rom, 0x1234, 0x10);
+        assert!(rom.iter().all(|&data| data == 0x00));
+        erase(&mut flash, &mut rom, 0x5555, 0x10);
+        assert!(rom.iter().all(|&data| data == 0xff));
+        assert_eq!(flash.save_data(&rom).len(), (ROM_SIZE / FLASH_SECTOR_SIZE) * (2 + FLASH_SECTOR_SIZE));
+    }
+
+    #[test]
+    fn software_id() {
+        let mut rom = vec![0x00; ROM_SIZE];
+        let mut flash = Flash::new(ROM_SIZE);
+        command(&mut flash, &mut rom, 0x90);
+        assert_eq!(flash.read(&rom, 0x0000), FLASH_MANUFACTURER_ID);
+        assert_eq!(flash.read(&rom, 0x0001), FLASH_DEVICE_ID);
+        flash.write(&mut rom, 0x0000, 0xf0);
+        assert_eq!(flash.read(&rom, 0x0000), 0x00);
+        assert_eq!(flash.read(&rom, 0x0001), 0x00);
+    }
+
+    #[test]
+    fn reset_backs_out_of_a_command() {
+        let mut rom = vec![0xff; ROM_SIZE];
+        let mut flash = Flash::new(ROM_SIZE);
+        flash.write(&mut rom, 0x5555, 0xaa);
+        flash.write(&mut rom, 0x2aaa, 0x55);
+        flash.write(&mut rom, 0x0000, 0xf0);
+        flash.write(&mut rom, 0x5555, 0xa0);
+        flash.write(&mut rom, 0x1234, 0x00);
+        assert_eq!(rom[0x1234], 0xff);
+        //Halfway through an erase too
+        command(&mut flash, &mut rom, 0x80);
+        flash.write(&mut rom, 0x5555, 0xf0);
+        flash.write(&mut rom, 0x5555, 0xaa);
+        flash.write(&mut rom, 0x2aaa, 0x55);
+        flash.write(&mut rom, 0x1000, 0x30);
+        program(&mut flash, &mut rom, 0x1234, 0x00);
+        assert_eq!(rom[0x1234], 0x00);
+        //But $F0 is just data once a program has been unlocked
+        program(&mut flash, &mut rom, 0x2000, 0xf0);
+        assert_eq!(rom[0x2000], 0xf0);
+    }
+
+    #[test]
+    fn save_data_round_trip() {
+        let original = vec![0xff; ROM_SIZE];
+        let mut rom = original.clone();
+        let mut flash = Flash::new(ROM_SIZE);
+        program(&mut flash, &mut rom, 0x1234, 0x12);
+        program(&mut flash, &mut rom, 0xf000, 0x34);
+        let data = flash.save_data(&rom);
+        //Only the two sectors that got written, each behind its sector number
+        assert_eq!(data.len(), 2 * (2 + FLASH_SECTOR_SIZE));
+        assert_eq!(&data[0..2], &[0x01, 0x00]);
+        assert_eq!(data[2 + 0x234], 0x12);
+        assert_eq!(&data[2 + FLASH_SECTOR_SIZE..4 + FLASH_SECTOR_SIZE], &[0x0f, 0x00]);
+        assert_eq!(data[4 + FLASH_SECTOR_SIZE], 0x34);
+
+        let mut loaded = original.clone();
+        let mut loaded_flash = Flash::new(ROM_SIZE);
+        loaded_flash.load_save_data(&mut loaded, &data);
+        assert_eq!(loaded, rom);
+        //Loaded sectors still differ from the ROM file, so they go out again on the next save
+        assert_eq!(loaded_flash.save_data(&loaded), data);
+        //Sectors past the end of the chip get skipped
+        let mut bad = vec![0x00, 0x01];
+        bad.extend_from_slice(&[0x00; FLASH_SECTOR_SIZE]);
+        loaded_flash.load_save_data(&mut loaded, &bad);
+        assert_eq!(loaded, rom);
+    }
+}
@@ -0,0 +1,324 @@
+/* Sunsoft FME-7, 5A and 5B, mapper 69 */
+//http://wiki.nesdev.com/w/index.php/Sunsoft_FME-7
+//Four 8k PRG banks (the one at $6000 can be ROM or RAM), eight 1k CHR banks, mirroring control and a 16-bit IRQ counter
+//that counts CPU cycles, all behind a command/parameter register pair at $8000/$A000.
+//The 5B is the same chip with a Yamaha YM2149 (an AY-3-8910 clone) bolted on, Gimmick! is the only game that uses it.
+//http://wiki.nesdev.com/w/index.php/Sunsoft_5B_audio
+
+use super::Mapper;
+use crate::rom::*;
+
+pub const FME7_PRG_BANK_SIZE: usize = 0x2000;
+pub const FME7_CHR_BANK_SIZE: usize = 0x0400;
+//The sound chip counts in steps of 16 CPU cycles
+pub const SUNSOFT5B_CLOCK_DIVIDER: u8 = 16;
+
+//Each volume step is 3dB, worked out ahead of time
+const VOLUME_TABLE: [f32; 16] = [
+    0.0, 0.0084, 0.0119, 0.0168, 0.0237, 0.0335, 0.0473, 0.0668, 0.0944, 0.1334, 0.1884, 0.2661,
+    0.3758, 0.5309, 0.7499, 1.0,
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct Sunsoft5bTone {
+    period: u16,
+    counter: u16,
+    is_high: bool,
+}
+
+impl Sunsoft5bTone {
+    fn step(&mut self) {
+        self.counter += 1;
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            self.is_high = !self.is_high;
+        }
+    }
+}
+
+//Envelope shapes come from 4 bits in register 13, continue, attack, alternate and hold
+#[derive(Clone, Debug, Default)]
+pub struct Sunsoft5bEnvelope {
+    period: u16,
+    counter: u32,
+    shape: u8,
+    //0-15 through one ramp
+    step: u8,
+    is_attack: bool,
+    is_holding: bool,
+}
+
+impl Sunsoft5bEnvelope {
+    fn write_shape(&mut self, data: u8) {
+        self.shape = data & 0x0f;
+        self.step = 0;
+        self.counter = 0;
+        self.is_attack = (self.shape & 0x04) == 0x04;
+        self.is_holding = false;
+    }
+    fn step(&mut self) {
+        if self.is_holding {
+            return;
+        }
+        //A whole 16 step ramp takes 512 * period CPU cycles
+        self.counter += 1;
+        if self.counter < (u32::from(self.period.max(1)) << 1) {
+            return;
+        }
+        self.counter = 0;
+        if self.step < 15 {
+            self.step += 1;
+            return;
+        }
+        //End of a ramp, what comes next depends on the shape
+        let is_continue = (self.shape & 0x08) == 0x08;
+        let is_alternate = (self.shape & 0x02) == 0x02;
+        let is_hold = (self.shape & 0x01) == 0x01;
+        if !is_continue {
+            //Drops to silence and stays there
+            self.is_attack = false;
+            self.is_holding = true;
+        } else if is_hold {
+            //Stays at the end of this ramp, or flips to the other end first if alternating
+            if is_alternate {
+                self.is_attack = !self.is_attack;
+            }
+            self.is_holding = true;
+        } else {
+            if is_alternate {
+                self.is_attack = !self.is_attack;
+            }
+            self.step = 0;
+        }
+    }
+    fn level(&self) -> u8 {
+        if self.is_holding {
+            if self.is_attack {
+                15
+            } else {
+                0
+            }
+        } else if self.is_attack {
+            self.step
+        } else {
+            15 - self.step
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Fme7 {
+    data: RomData,
+    //$8000, which register $A000 writes to
+    command: u8,
+    chr_banks: [u8; 8],
+    //Command 8, the $6000 window. Bit 7 enables RAM, bit 6 picks RAM over ROM
+    sram_bank: u8,
+    //Commands 9-B
+    prg_banks: [u8; 3],
+    mirroring: u8,
+    //Command D, bit 0 lets the counter raise an IRQ and bit 7 makes it count
+    irq_control: u8,
+    irq_counter: u16,
+    is_irq: bool,
+
+    //$C000, which sound register $E000 writes to
+    audio_reg: u8,
+    tones: [Sunsoft5bTone; 3],
+    noise_period: u8,
+    noise_counter: u8,
+    //17-bit LFSR, same as the AY
+    noise_shift: u32,
+    //Register 7, bits 0-2 turn tone off and bits 3-5 turn noise off for each channel
+    audio_mixer: u8,
+    //Registers 8-10, bit 4 hands the volume over to the envelope
+    volumes: [u8; 3],
+    envelope: Sunsoft5bEnvelope,
+    audio_divider: u8,
+}
+
+impl Fme7 {
+    pub fn new(data: RomData) -> Self {
+        Self {
+            data,
+            command: 0,
+            chr_banks: [0; 8],
+            sram_bank: 0,
+            prg_banks: [0; 3],
+            mirroring: 0,
+            irq_control: 0,
+            irq_counter: 0,
+            is_irq: false,
+            audio_reg: 0,
+            tones: [Sunsoft5bTone::default(), Sunsoft5bTone::default(), Sunsoft5bTone::default()],
+            noise_period: 0,
+            noise_counter: 0,
+            noise_shift: 1,
+            audio_mixer: 0xff,
+            volumes: [0; 3],
+            envelope: Sunsoft5bEnvelope::default(),
+            audio_divider: 0,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / FME7_PRG_BANK_SIZE;
+        let bank = match addr >> 13 {
+            3 => usize::from(self.sram_bank & 0x3f),
+            4 => usize::from(self.prg_banks[0] & 0x3f),
+            5 => usize::from(self.prg_banks[1] & 0x3f),
+            6 => usize::from(self.prg_banks[2] & 0x3f),
+            _ => num_of_banks.saturating_sub(1),
+        };
+        let offset = usize::from(addr) & (FME7_PRG_BANK_SIZE - 1);
+        (bank * FME7_PRG_BANK_SIZE + offset) % self.data.p_rom.len()
+    }
+    fn sram_index(&self, addr: u16) -> usize {
+        let bank = usize::from(self.sram_bank & 0x3f);
+        let offset = usize::from(addr) & (FME7_PRG_BANK_SIZE - 1);
+        (bank * FME7_PRG_BANK_SIZE + offset) % self.data.srambytes.len()
+    }
+    fn is_sram_selected(&self) -> bool {
+        (self.sram_bank & 0x40) == 0x40
+    }
+    fn is_sram_enable(&self) -> bool {
+        (self.sram_bank & 0x80) == 0x80
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = usize::from(self.chr_banks[usize::from(addr >> 10) & 0x07]);
+        bank * FME7_CHR_BANK_SIZE + (usize::from(addr) & (FME7_CHR_BANK_SIZE - 1))
+    }
+    fn write_parameter(&mut self, data: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks[usize::from(self.command)] = data,
+            0x8 => self.sram_bank = data,
+            0x9..=0xb => self.prg_banks[usize::from(self.command - 0x9)] = data,
+            0xc => self.mirroring = data & 0x03,
+            0xd => {
+                //Any write here acknowledges
+                self.irq_control = data;
+                self.is_irq = false;
+            }
+            0xe => self.irq_counter = (self.irq_counter & 0xff00) | u16::from(data),
+            _ => self.irq_counter = (self.irq_counter & 0x00ff) | (u16::from(data) << 8),
+        }
+    }
+    fn write_audio(&mut self, data: u8) {
+        match self.audio_reg {
+            0x0 | 0x2 | 0x4 => {
+                let tone = &mut self.tones[usize::from(self.audio_reg >> 1)];
+                tone.period = (tone.period & 0x0f00) | u16::from(data);
+            }
+            0x1 | 0x3 | 0x5 => {
+                let tone = &mut self.tones[usize::from(self.audio_reg >> 1)];
+                tone.period = (tone.period & 0x00ff) | (u16::from(data & 0x0f) << 8);
+            }
+            0x6 => self.noise_period = data & 0x1f,
+            0x7 => self.audio_mixer = data,
+            0x8..=0xa => self.volumes[usize::from(self.audio_reg - 0x8)] = data & 0x1f,
+            0xb => self.envelope.period = (self.envelope.period & 0xff00) | u16::from(data),
+            0xc => self.envelope.period = (self.envelope.period & 0x00ff) | (u16::from(data) << 8),
+            0xd => self.envelope.write_shape(data),
+            _ => {}
+        }
+    }
+    fn step_audio(&mut self) {
+        for tone in self.tones.iter_mut() {
+            tone.step();
+        }
+        //Noise runs at half the rate of a tone with the same period
+        self.noise_counter += 1;
+        if self.noise_counter >= (self.noise_period.max(1) << 1) {
+            self.noise_counter = 0;
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 0x01;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+        self.envelope.step();
+    }
+}
+
+impl Mapper for Fme7 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR && self.is_sram_selected() {
+            if self.is_sram_enable() {
+                self.data.srambytes[self.sram_index(addr)]
+            } else {
+                open_bus(addr)
+            }
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_sram_selected() && self.is_sram_enable() {
+                let index = self.sram_index(addr);
+                self.data.srambytes[index] = data;
+            }
+            return;
+        }
+        match addr & 0xe000 {
+            0x8000 => self.command = data & 0x0f,
+            0xa000 => self.write_parameter(data),
+            0xc000 => self.audio_reg = data & 0x0f,
+            _ => self.write_audio(data),
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.mirroring {
+            0 => MirrorTable::Vertical,
+            1 => MirrorTable::Horizontal,
+            2 => MirrorTable::SingleScreenLower,
+            _ => MirrorTable::SingleScreenUpper,
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.is_irq
+    }
+    fn step_cpu_cycle(&mut self) {
+        if (self.irq_control & 0x80) == 0x80 {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xffff && (self.irq_control & 0x01) == 0x01 {
+                self.is_irq = true;
+            }
+        }
+        self.audio_divider += 1;
+        if self.audio_divider >= SUNSOFT5B_CLOCK_DIVIDER {
+            self.audio_divider = 0;
+            self.step_audio();
+        }
+    }
+    fn audio_output(&self) -> f32 {
+        let is_noise_high = (self.noise_shift & 0x01) == 0x01;
+        let mut level = 0.0;
+        for (index, tone) in self.tones.iter().enumerate() {
+            let is_tone_off = (self.audio_mixer >> index) & 0x01 == 0x01;
+            let is_noise_off = (self.audio_mixer >> (index + 3)) & 0x01 == 0x01;
+            if (tone.is_high || is_tone_off) && (is_noise_high || is_noise_off) {
+                let volume = if (self.volumes[index] & 0x10) == 0x10 {
+                    self.envelope.level()
+                } else {
+                    self.volumes[index] & 0x0f
+                };
+                level += VOLUME_TABLE[usize::from(volume)];
+            }
+        }
+        //The 5B is a lot louder than the console, turn it down to sit with everything else
+        level * 0.06
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.data.battery_save_data()
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.data.load_battery_save_data(data);
+    }
+}
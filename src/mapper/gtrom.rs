@@ -0,0 +1,161 @@
+/* GTROM (Cheapocabra), mapper 111 */
+//http://wiki.nesdev.com/w/index.php/GTROM
+//Membler Industries' homebrew board. 512k of self flashing PRG in 32k banks, and one 32k RAM chip split between two 8k
+//CHR pages and two pages of four screen nametables. One register at $5000-$5FFF (mirrored at $7000-$7FFF) does it all:
+//  RGNCPPPP
+//PPPP is the PRG bank, C the CHR page and N the nametable page. G and R only drive the green and red LEDs on the cart, so they're ignored.
+//The flash is the same SST39SF040 as UNROM 512 and always writable, commands go through $8000-$FFFF in the current bank.
+
+use super::bank::bank_index;
+use super::flash::Flash;
+use super::Mapper;
+use crate::rom::*;
+use crate::video::*;
+
+pub const GTROM_PRG_BANK_SIZE: usize = 0x8000;
+pub const GTROM_CHR_BANK_SIZE: usize = 0x2000;
+//Nametables take the upper half of the RAM chip, 8k a page with only the first 4k used
+pub const GTROM_NAME_TABLE_BASE: usize = 0x4000;
+pub const GTROM_NAME_TABLE_PAGE_SIZE: usize = 0x2000;
+pub const GTROM_RAM_SIZE: usize = 0x8000;
+
+#[derive(Debug)]
+pub struct Gtrom {
+    data: RomData,
+    reg: u8,
+    flash: Flash,
+}
+
+impl Gtrom {
+    pub fn new(mut data: RomData) -> Self {
+        //CHR and nametables share the one chip, so c_ram holds both
+        if data.c_ram.len() < GTROM_RAM_SIZE {
+            data.c_ram.resize(GTROM_RAM_SIZE, 0);
+        }
+        let flash = Flash::new(data.p_rom.len());
+        Self { data, reg: 0, flash }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        bank_index(usize::from(self.reg & 0x0f), GTROM_PRG_BANK_SIZE, addr, self.data.p_rom.len())
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        usize::from((self.reg >> 4) & 0x01) * GTROM_CHR_BANK_SIZE + usize::from(addr)
+    }
+    fn nametable_index(&self, addr: u16) -> usize {
+        let page = usize::from((self.reg >> 5) & 0x01);
+        GTROM_NAME_TABLE_BASE + page * GTROM_NAME_TABLE_PAGE_SIZE + (usize::from(addr) & 0x0fff)
+    }
+}
+
+impl Mapper for Gtrom {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            //The register is write only and there's no PRG RAM, open bus
+            open_bus(addr)
+        } else {
+            let index = self.prg_rom_index(addr);
+            self.flash.read(&self.data.p_rom, index)
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        match addr {
+            0x5000..=0x5fff | 0x7000..=0x7fff => self.reg = data,
+            0x8000..=0xffff => {
+                let index = self.prg_rom_index(addr);
+                self.flash.write(&mut self.data.p_rom, index, data);
+            }
+            _ => {}
+        }
+    }
+    //The board register answers at $5000-$5FFF as well as $7000-$7FFF
+    fn is_expansion_area(&self) -> bool {
+        true
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr_ram(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr_ram(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        MirrorTable::FourScreen
+    }
+    fn read_nametable_u8(&mut self, _nametables: &NameTables, addr: u16) -> u8 {
+        self.data.read_chr_ram(self.nametable_index(addr))
+    }
+    fn write_nametable_u8(&mut self, _nametables: &mut NameTables, addr: u16, data: u8) {
+        let index = self.nametable_index(addr);
+        self.data.write_chr_ram(index, data);
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.flash.save_data(&self.data.p_rom))
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.flash.load_save_data(&mut self.data.p_rom, data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapper::flash::FLASH_SECTOR_SIZE;
+
+    //512k of PRG where every byte of a 32k bank holds that bank's number
+    fn new_mapper() -> Gtrom {
+        let p_rom = (0..16).flat_map(|bank| vec![bank as u8; GTROM_PRG_BANK_SIZE]).collect();
+        Gtrom::new(RomData { mapper_id: 111, p_rom, ..RomData::default() })
+    }
+    //The command addresses are $5555/$2AAA inside the chip, which is $D555/$AAAA through the current bank
+    fn program(mapper: &mut Gtrom, addr: u16, data: u8) {
+        mapper.write_u8(0xd555, 0xaa, false);
+        mapper.write_u8(0xaaaa, 0x55, false);
+        mapper.write_u8(0xd555, 0xa0, false);
+        mapper.write_u8(addr, data, false);
+    }
+
+    #[test]
+    fn register() {
+        let mut mapper = new_mapper();
+        mapper.write_u8(0x5000, 0x03, false);
+        assert_eq!(mapper.read_u8(0x8000, false), 3);
+        //Mirrored at $7000, and the LED bits don't get in the way
+        mapper.write_u8(0x7fff, 0xc5, false);
+        assert_eq!(mapper.read_u8(0xffff, false), 5);
+        //CHR and nametable pages share the one RAM chip
+        mapper.write_u8(0x5000, 0x10, false);
+        mapper.write_video_u8(0x0000, 0x11);
+        mapper.write_u8(0x5000, 0x20, false);
+        let mut nametables: NameTables = [[0; NAME_TABLE_SIZE]; NUM_OF_NAME_TABLE];
+        mapper.write_nametable_u8(&mut nametables, 0x2c00, 0x22);
+        assert_eq!(mapper.data.c_ram[GTROM_CHR_BANK_SIZE], 0x11);
+        assert_eq!(mapper.data.c_ram[GTROM_NAME_TABLE_BASE + GTROM_NAME_TABLE_PAGE_SIZE + 0x0c00], 0x22);
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2c00), 0x22);
+        mapper.write_u8(0x5000, 0x00, false);
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2c00), 0x00);
+    }
+
+    #[test]
+    fn flash_save() {
+        let mut mapper = new_mapper();
+        mapper.write_u8(0x5000, 0x02, false);
+        program(&mut mapper, 0x9234, 0x00);
+        assert_eq!(mapper.read_u8(0x9234, false), 0x00);
+        assert_eq!(mapper.read_u8(0x9235, false), 0x02);
+        //Bank 2's second sector
+        let data = mapper.save_data().unwrap();
+        let sector = (2 * GTROM_PRG_BANK_SIZE + 0x1000) / FLASH_SECTOR_SIZE;
+        assert_eq!(&data[0..2], &(sector as u16).to_le_bytes());
+        assert_eq!(data.len(), 2 + FLASH_SECTOR_SIZE);
+
+        let mut loaded = new_mapper();
+        loaded.load_save_data(&data);
+        loaded.write_u8(0x5000, 0x02, false);
+        assert_eq!(loaded.read_u8(0x9234, false), 0x00);
+        loaded.write_u8(0x5000, 0x03, false);
+        assert_eq!(loaded.read_u8(0x9234, false), 0x03);
+    }
+}
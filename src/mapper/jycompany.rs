@@ -0,0 +1,531 @@
+/* J.Y. Company ASIC, mappers 90, 209 and 211 */
+//http://wiki.nesdev.com/w/index.php/J.Y._Company_ASIC
+//Taiwanese pirate chip that does a bit of everything. Four PRG modes (32k/16k/8k, plus 8k with the bank bits reversed),
+//four CHR modes (8k/4k/2k/1k) with 16-bit bank numbers and an optional MMC4 style latch, CHR-ROM nametables, a multiplier,
+//and an IRQ counter that can count up or down on CPU cycles, PPU A12 rises, PPU reads or CPU writes through a prescaler.
+//  90: no ROM nametables, plain mirroring from $D001
+//  209: ROM nametables when $D000 bit 5 says so
+//  211: ROM nametables always
+//Register map:
+//  $5000: DIP switches (bits 6-7), $5800/$5801: multiplier, $5803: one byte of RAM
+//  $8000-$8003: PRG banks, $9000-$9007/$A000-$A007: CHR banks low/high, $B000-$B003/$B004-$B007: nametables low/high
+//  $C000-$C007: IRQ, $D000: modes, $D001: mirroring, $D002: nametable RAM select, $D003: outer banks
+
+use super::Mapper;
+use crate::rom::*;
+use crate::video::*;
+
+pub const JYCOMPANY_PRG_BANK_SIZE: usize = 0x2000;
+pub const JYCOMPANY_CHR_BANK_SIZE: usize = 0x0400;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JyCompanyBoard {
+    //Mapper 90
+    Jy90,
+    //Mapper 209
+    Jy209,
+    //Mapper 211
+    Jy211,
+}
+
+//What clocks the IRQ prescaler, $C001 bits 0-1
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum IrqSource {
+    CpuCycle,
+    PpuA12,
+    PpuRead,
+    CpuWrite,
+}
+
+//Where a nametable address ends up with ROM nametables on
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum NametableSource {
+    //Index into CHR-ROM
+    Chr(usize),
+    //One of the console's nametable pages
+    Ciram(usize),
+}
+
+#[derive(Debug)]
+pub struct JyCompany {
+    data: RomData,
+    board: JyCompanyBoard,
+    prg_banks: [u8; 4],
+    chr_low: [u8; 8],
+    chr_high: [u8; 8],
+    nt_low: [u8; 4],
+    nt_high: [u8; 4],
+    //$D000: bits 0-1 PRG mode, bit 2 last PRG bank switchable, bits 3-4 CHR mode, bit 5 ROM nametables,
+    //bit 6 ROM nametables everywhere, bit 7 PRG ROM at $6000
+    mode: u8,
+    //$D001
+    mirroring: u8,
+    //$D002, bit 7 is compared against bit 7 of each nametable register to pick RAM or ROM
+    nt_ram_select: u8,
+    //$D003: bit 0 and bits 3-4 are the outer CHR block, bits 1-2 the outer PRG block, bit 5 CHR block mode, bit 7 the CHR latch
+    outer_bank: u8,
+    //MMC4 style latches for the two pattern tables in 4k mode, true for $FE
+    chr_latches: [bool; 2],
+    multiplicand: u8,
+    multiplier: u8,
+    ram_byte: u8,
+    //$C001: bits 0-1 source, bit 2 3-bit prescaler, bits 6-7 direction (1 up, 2 down, anything else stopped)
+    irq_mode: u8,
+    irq_prescaler: u8,
+    irq_counter: u8,
+    //$C006, XORed into prescaler and counter writes
+    irq_xor: u8,
+    is_irq_enable: bool,
+    is_irq: bool,
+    last_a12: bool,
+}
+
+impl JyCompany {
+    pub fn new(data: RomData, board: JyCompanyBoard) -> Self {
+        Self {
+            data,
+            board,
+            prg_banks: [0; 4],
+            chr_low: [0; 8],
+            chr_high: [0; 8],
+            nt_low: [0; 4],
+            nt_high: [0; 4],
+            mode: 0,
+            mirroring: 0,
+            nt_ram_select: 0,
+            outer_bank: 0,
+            chr_latches: [false; 2],
+            multiplicand: 0,
+            multiplier: 0,
+            ram_byte: 0,
+            irq_mode: 0,
+            irq_prescaler: 0,
+            irq_counter: 0,
+            irq_xor: 0,
+            is_irq_enable: false,
+            is_irq: false,
+            last_a12: false,
+        }
+    }
+    //PRG bank in 8k units for one of the four 8k slots from $8000, or slot 4 for $6000
+    fn prg_bank(&self, slot: usize) -> usize {
+        let prg_mode = self.mode & 0x03;
+        let reg = |index: usize| {
+            let bank = self.prg_banks[index] & 0x7f;
+            //Mode 3 is mode 2 with the bank number wired backwards
+            if prg_mode == 3 {
+                usize::from(bank.reverse_bits() >> 1)
+            } else {
+                usize::from(bank)
+            }
+        };
+        let is_last_switchable = (self.mode & 0x04) == 0x04;
+        let bank = match (prg_mode, slot) {
+            //32k, register 3 picks the bank
+            (0, 4) => (reg(3) << 2) | 0x03,
+            (0, _) if is_last_switchable => (reg(3) << 2) | slot,
+            (0, _) => 0x3c | slot,
+            //16k, register 1 at $8000 and register 3 at $C000
+            (1, 4) => (reg(3) << 1) | 0x01,
+            (1, 0) | (1, 1) => (reg(1) << 1) | (slot & 0x01),
+            (1, _) if is_last_switchable => (reg(3) << 1) | (slot & 0x01),
+            (1, _) => 0x3e | (slot & 0x01),
+            //8k
+            (_, 4) => reg(3),
+            (_, 3) if !is_last_switchable => 0x3f,
+            (_, slot) => reg(slot),
+        };
+        //Outer 512k block on top
+        (bank & 0x3f) | (usize::from((self.outer_bank >> 1) & 0x03) << 6)
+    }
+    fn prg_rom_index(&self, slot: usize, addr: u16) -> usize {
+        let bank = self.prg_bank(slot);
+        (bank * JYCOMPANY_PRG_BANK_SIZE + (usize::from(addr) & (JYCOMPANY_PRG_BANK_SIZE - 1))) % self.data.p_rom.len()
+    }
+    //A CHR register in units of the current CHR mode's bank size
+    fn chr_reg(&self, index: usize) -> usize {
+        let chr_mode = (self.mode >> 3) & 0x03;
+        if (self.outer_bank & 0x20) == 0x20 {
+            //Block mode, the low byte picks inside a 256k block and $D003 picks the block
+            let block = usize::from((self.outer_bank & 0x01) | ((self.outer_bank >> 2) & 0x06));
+            let mask = 0xff >> (3 - chr_mode);
+            (usize::from(self.chr_low[index]) & mask) | (block << (5 + chr_mode))
+        } else {
+            usize::from(self.chr_low[index]) | (usize::from(self.chr_high[index]) << 8)
+        }
+    }
+    //CHR bank in 1k units for a pattern table address
+    fn chr_bank(&self, addr: u16) -> usize {
+        let slot = usize::from(addr >> 10) & 0x07;
+        match (self.mode >> 3) & 0x03 {
+            0 => (self.chr_reg(0) << 3) | slot,
+            1 => {
+                let table = slot >> 2;
+                //With the latch on, each 4k half flips between two registers on tiles $FD/$FE like the MMC4
+                let is_latched = (self.outer_bank & 0x80) == 0x80 && self.chr_latches[table];
+                let index = (table << 2) | if is_latched { 0x02 } else { 0x00 };
+                (self.chr_reg(index) << 2) | (slot & 0x03)
+            }
+            2 => (self.chr_reg(slot & 0x06) << 1) | (slot & 0x01),
+            _ => self.chr_reg(slot),
+        }
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        self.chr_bank(addr) * JYCOMPANY_CHR_BANK_SIZE + (usize::from(addr) & (JYCOMPANY_CHR_BANK_SIZE - 1))
+    }
+    fn update_chr_latch(&mut self, addr: u16) {
+        let table = usize::from(addr >> 12) & 0x01;
+        match addr & 0x0ff8 {
+            0x0fd8 => self.chr_latches[table] = false,
+            0x0fe8 => self.chr_latches[table] = true,
+            _ => {}
+        }
+    }
+    fn is_rom_nametables(&self) -> bool {
+        match self.board {
+            JyCompanyBoard::Jy90 => false,
+            JyCompanyBoard::Jy209 => (self.mode & 0x20) == 0x20,
+            JyCompanyBoard::Jy211 => true,
+        }
+    }
+    fn nametable_source(&self, addr: u16) -> NametableSource {
+        let index = usize::from(addr >> 10) & 0x03;
+        let is_rom = (self.mode & 0x40) == 0x40 || ((self.nt_low[index] ^ self.nt_ram_select) & 0x80) == 0x80;
+        if is_rom {
+            let bank = usize::from(self.nt_low[index]) | (usize::from(self.nt_high[index]) << 8);
+            NametableSource::Chr(bank * JYCOMPANY_CHR_BANK_SIZE + (usize::from(addr) & (JYCOMPANY_CHR_BANK_SIZE - 1)))
+        } else {
+            NametableSource::Ciram(usize::from(self.nt_low[index] & 0x01))
+        }
+    }
+    fn irq_source(&self) -> IrqSource {
+        match self.irq_mode & 0x03 {
+            0 => IrqSource::CpuCycle,
+            1 => IrqSource::PpuA12,
+            2 => IrqSource::PpuRead,
+            _ => IrqSource::CpuWrite,
+        }
+    }
+    fn clock_irq(&mut self, source: IrqSource) {
+        if self.irq_source() != source {
+            return;
+        }
+        let direction = self.irq_mode >> 6;
+        let mask = if (self.irq_mode & 0x04) == 0x04 { 0x07 } else { 0xff };
+        let mut prescaler = self.irq_prescaler & mask;
+        let is_carry = match direction {
+            1 => {
+                prescaler = prescaler.wrapping_add(1);
+                (prescaler & mask) == 0
+            }
+            2 => {
+                prescaler = prescaler.wrapping_sub(1);
+                (prescaler & mask) == mask
+            }
+            _ => return,
+        };
+        self.irq_prescaler = (self.irq_prescaler & !mask) | (prescaler & mask);
+        if !is_carry {
+            return;
+        }
+        //The counter wraps past the end in whichever direction it's going, and that's the IRQ
+        let is_wrap = if direction == 1 {
+            self.irq_counter = self.irq_counter.wrapping_add(1);
+            self.irq_counter == 0x00
+        } else {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            self.irq_counter == 0xff
+        };
+        if is_wrap && self.is_irq_enable {
+            self.is_irq = true;
+        }
+    }
+    fn watch_ppu_read(&mut self, addr: u16) {
+        self.clock_irq(IrqSource::PpuRead);
+        let a12 = (addr & 0x1000) == 0x1000;
+        if a12 && !self.last_a12 {
+            self.clock_irq(IrqSource::PpuA12);
+        }
+        self.last_a12 = a12;
+    }
+    fn write_irq(&mut self, addr: u16, data: u8) {
+        match addr & 0x07 {
+            0 => {
+                self.is_irq_enable = (data & 0x01) == 0x01;
+                if !self.is_irq_enable {
+                    self.is_irq = false;
+                }
+            }
+            1 => self.irq_mode = data,
+            2 => {
+                self.is_irq_enable = false;
+                self.is_irq = false;
+            }
+            3 => self.is_irq_enable = true,
+            4 => self.irq_prescaler = data ^ self.irq_xor,
+            5 => self.irq_counter = data ^ self.irq_xor,
+            6 => self.irq_xor = data,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for JyCompany {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        match addr {
+            //DIP switches all off
+            0x5000..=0x57ff => open_bus(addr) & 0x3f,
+            0x5800..=0x5fff => {
+                let product = u16::from(self.multiplicand) * u16::from(self.multiplier);
+                match addr & 0x03 {
+                    0 => product as u8,
+                    1 => (product >> 8) as u8,
+                    3 => self.ram_byte,
+                    _ => open_bus(addr),
+                }
+            }
+            0x6000..=0x7fff => {
+                if (self.mode & 0x80) == 0x80 {
+                    self.data.p_rom[self.prg_rom_index(4, addr)]
+                } else {
+                    let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                    self.data.srambytes[index]
+                }
+            }
+            0x8000..=0xffff => {
+                let slot = usize::from((addr >> 13) & 0x03);
+                self.data.p_rom[self.prg_rom_index(slot, addr)]
+            }
+            _ => open_bus(addr),
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        self.clock_irq(IrqSource::CpuWrite);
+        match addr {
+            0x5800..=0x5fff => match addr & 0x03 {
+                0 => self.multiplicand = data,
+                1 => self.multiplier = data,
+                3 => self.ram_byte = data,
+                _ => {}
+            },
+            //RAM unless PRG ROM has been put there
+            0x6000..=0x7fff if (self.mode & 0x80) == 0x00 => {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index] = data;
+            }
+            0x8000..=0x8fff => self.prg_banks[usize::from(addr & 0x03)] = data,
+            0x9000..=0x9fff => self.chr_low[usize::from(addr & 0x07)] = data,
+            0xa000..=0xafff => self.chr_high[usize::from(addr & 0x07)] = data,
+            0xb000..=0xbfff => {
+                let index = usize::from(addr & 0x03);
+                if (addr & 0x04) == 0x00 {
+                    self.nt_low[index] = data;
+                } else {
+                    self.nt_high[index] = data;
+                }
+            }
+            0xc000..=0xcfff => self.write_irq(addr, data),
+            0xd000..=0xdfff => match addr & 0x03 {
+                0 => self.mode = data,
+                1 => self.mirroring = data & 0x03,
+                2 => self.nt_ram_select = data,
+                _ => self.outer_bank = data,
+            },
+            _ => {}
+        }
+    }
+    //DIP switches, the multiplier and the spare RAM byte
+    fn is_expansion_area(&self) -> bool {
+        true
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.watch_ppu_read(addr);
+        let data = self.data.read_chr(self.chr_index(addr));
+        self.update_chr_latch(addr);
+        data
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.mirroring {
+            0 => MirrorTable::Vertical,
+            1 => MirrorTable::Horizontal,
+            2 => MirrorTable::SingleScreenLower,
+            _ => MirrorTable::SingleScreenUpper,
+        }
+    }
+    fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        self.watch_ppu_read(addr);
+        if self.is_rom_nametables() {
+            match self.nametable_source(addr) {
+                NametableSource::Chr(index) => self.data.read_chr(index),
+                NametableSource::Ciram(page) => nametables[page][usize::from(addr) & (NAME_TABLE_SIZE - 1)],
+            }
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset]
+        }
+    }
+    fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        if self.is_rom_nametables() {
+            //Writes to ROM nametables go nowhere
+            if let NametableSource::Ciram(page) = self.nametable_source(addr) {
+                nametables[page][usize::from(addr) & (NAME_TABLE_SIZE - 1)] = data;
+            }
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset] = data;
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.is_irq
+    }
+    fn step_cpu_cycle(&mut self) {
+        self.clock_irq(IrqSource::CpuCycle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //512k PRG where every byte of a bank holds that bank's number, and 8k of CHR
+    fn new_mapper() -> JyCompany {
+        let p_rom = (0..64).flat_map(|bank| vec![bank as u8; JYCOMPANY_PRG_BANK_SIZE]).collect();
+        let c_rom = vec![0; 8 * JYCOMPANY_CHR_BANK_SIZE];
+        JyCompany::new(RomData { mapper_id: 90, p_rom, c_rom, ..RomData::default() }, JyCompanyBoard::Jy90)
+    }
+    fn prg_banks(mapper: &mut JyCompany) -> [u8; 4] {
+        [0x8000, 0xa000, 0xc000, 0xe000].map(|addr| mapper.read_u8(addr, false))
+    }
+    //The mode goes in last, while the source is still CPU cycles none of these writes clock the counter
+    fn setup_irq(mapper: &mut JyCompany, irq_mode: u8, prescaler: u8, counter: u8) {
+        mapper.write_u8(0xc004, prescaler, false);
+        mapper.write_u8(0xc005, counter, false);
+        mapper.write_u8(0xc003, 0, false);
+        mapper.write_u8(0xc001, irq_mode, false);
+    }
+    fn step_cpu_cycles(mapper: &mut JyCompany, count: usize) {
+        for _ in 0..count {
+            mapper.step_cpu_cycle();
+        }
+    }
+
+    #[test]
+    fn prg_mode_3_reverses_bank_bits() {
+        let mut mapper = new_mapper();
+        mapper.write_u8(0x8000, 0x02, false);
+        mapper.write_u8(0x8001, 0x04, false);
+        mapper.write_u8(0x8002, 0x10, false);
+        mapper.write_u8(0xd000, 0x02, false);
+        assert_eq!(prg_banks(&mut mapper), [0x02, 0x04, 0x10, 0x3f]);
+        mapper.write_u8(0xd000, 0x03, false);
+        assert_eq!(prg_banks(&mut mapper), [0x20, 0x10, 0x04, 0x3f]);
+    }
+
+    #[test]
+    fn irq_counts_up_and_wraps() {
+        let mut mapper = new_mapper();
+        setup_irq(&mut mapper, 0x40, 0xfe, 0xff);
+        step_cpu_cycles(&mut mapper, 1);
+        assert!(!mapper.is_irq());
+        step_cpu_cycles(&mut mapper, 1);
+        assert!(mapper.is_irq());
+        assert_eq!(mapper.irq_counter, 0x00);
+        //$C002 acknowledges and disables
+        mapper.write_u8(0xc002, 0, false);
+        assert!(!mapper.is_irq());
+    }
+
+    #[test]
+    fn irq_counts_down_and_wraps() {
+        let mut mapper = new_mapper();
+        setup_irq(&mut mapper, 0x80, 0x01, 0x00);
+        step_cpu_cycles(&mut mapper, 1);
+        assert!(!mapper.is_irq());
+        step_cpu_cycles(&mut mapper, 1);
+        assert!(mapper.is_irq());
+        assert_eq!(mapper.irq_counter, 0xff);
+    }
+
+    #[test]
+    fn irq_stopped_direction() {
+        let mut mapper = new_mapper();
+        for irq_mode in [0x00, 0xc0] {
+            setup_irq(&mut mapper, irq_mode, 0xff, 0xff);
+            step_cpu_cycles(&mut mapper, 0x1000);
+            assert!(!mapper.is_irq());
+            assert_eq!(mapper.irq_counter, 0xff);
+        }
+    }
+
+    #[test]
+    fn irq_prescaler_mask() {
+        let mut mapper = new_mapper();
+        //8-bit prescaler, 256 cycles a count
+        setup_irq(&mut mapper, 0x40, 0x00, 0xfe);
+        step_cpu_cycles(&mut mapper, 511);
+        assert!(!mapper.is_irq());
+        step_cpu_cycles(&mut mapper, 1);
+        assert!(mapper.is_irq());
+        //3-bit prescaler, 8 cycles a count and the top bits are left alone
+        mapper.write_u8(0xc002, 0, false);
+        setup_irq(&mut mapper, 0x44, 0xf0, 0xfe);
+        step_cpu_cycles(&mut mapper, 15);
+        assert!(!mapper.is_irq());
+        step_cpu_cycles(&mut mapper, 1);
+        assert!(mapper.is_irq());
+        assert_eq!(mapper.irq_prescaler, 0xf0);
+    }
+
+    #[test]
+    fn irq_source_ppu_a12() {
+        let mut mapper = new_mapper();
+        setup_irq(&mut mapper, 0x45, 0x07, 0xff);
+        step_cpu_cycles(&mut mapper, 16);
+        for _ in 0..8 {
+            mapper.read_video_u8(0x0000);
+        }
+        assert!(!mapper.is_irq());
+        //Only the rise counts
+        mapper.read_video_u8(0x1000);
+        assert!(mapper.is_irq());
+        assert_eq!(mapper.irq_prescaler, 0x00);
+        mapper.read_video_u8(0x1000);
+        assert_eq!(mapper.irq_prescaler, 0x00);
+    }
+
+    #[test]
+    fn irq_source_ppu_read() {
+        let mut mapper = new_mapper();
+        setup_irq(&mut mapper, 0x46, 0x06, 0xff);
+        step_cpu_cycles(&mut mapper, 16);
+        mapper.read_video_u8(0x0000);
+        assert!(!mapper.is_irq());
+        mapper.read_video_u8(0x0000);
+        assert!(mapper.is_irq());
+    }
+
+    #[test]
+    fn irq_source_cpu_write() {
+        let mut mapper = new_mapper();
+        setup_irq(&mut mapper, 0x47, 0x00, 0xff);
+        step_cpu_cycles(&mut mapper, 16);
+        for _ in 0..7 {
+            mapper.write_u8(0x6000, 0, false);
+        }
+        assert!(!mapper.is_irq());
+        mapper.write_u8(0x6000, 0, false);
+        assert!(mapper.is_irq());
+        //Nondestructive writes from the debugger don't count
+        mapper.write_u8(0xc000, 1, false);
+        assert_eq!(mapper.irq_prescaler, 0x01);
+        mapper.write_u8(0x6000, 0, true);
+        assert_eq!(mapper.irq_prescaler, 0x01);
+    }
+}
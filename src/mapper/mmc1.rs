@@ -0,0 +1,170 @@
+/* MMC1, mapper 1 */
+//http://wiki.nesdev.com/w/index.php/MMC1
+//Nintendo's first real mapper chip, Zelda, Metroid and Final Fantasy all use it.
+//The CPU can't write the registers directly, it has to feed them one bit at a time through a 5 bit shift register.
+
+use super::Mapper;
+use crate::rom::*;
+
+pub const MMC1_PRG_BANK_SIZE: usize = 0x4000;
+pub const MMC1_CHR_BANK_SIZE: usize = 0x1000;
+//SUROM/SXROM carts have 512k of PRG, which is two 256k halves picked by a CHR register line
+pub const MMC1_PRG_OUTER_BANK_SIZE: usize = 0x40000;
+
+#[derive(Debug)]
+pub struct Mmc1 {
+    data: RomData,
+    //Serial port, a 1 in bit 5 means the fifth write is on its way in
+    shift_reg: u8,
+    //$8000-$9FFF
+    control: u8,
+    //$A000-$BFFF
+    chr_bank0: u8,
+    //$C000-$DFFF
+    chr_bank1: u8,
+    //$E000-$FFFF
+    prg_bank: u8,
+}
+
+impl Mmc1 {
+    pub fn new(data: RomData) -> Self {
+        Self {
+            data,
+            shift_reg: 0x10,
+            //Power on state keeps the last bank at $C000, which is where the reset vector lives
+            control: 0x0c,
+            chr_bank0: 0,
+            chr_bank1: 0,
+            prg_bank: 0,
+        }
+    }
+    fn is_sram_enable(&self) -> bool {
+        //The SxROM boards reuse the CHR bank lines for other things when the cart has CHR RAM
+        //SNROM also disables RAM with bit 4 of the CHR bank, SUROM needs that bit for PRG instead
+        let is_snrom_disable = self.data.is_chr_ram()
+            && self.data.p_rom.len() <= MMC1_PRG_OUTER_BANK_SIZE
+            && (self.chr_bank0 & 0x10) == 0x10;
+        (self.prg_bank & 0x10) == 0x00 && !is_snrom_disable
+    }
+    fn sram_index(&self, addr: u16) -> usize {
+        let offset = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+        //SOROM has 16k of RAM and SXROM 32k, both get banked by the CHR bank register
+        let bank = match self.data.srambytes.len() {
+            0x4000 => usize::from((self.chr_bank0 >> 3) & 0x01),
+            0x8000 => usize::from((self.chr_bank0 >> 2) & 0x03),
+            _ => 0,
+        };
+        (bank * BATTERY_PACKED_RAM_MAX_SIZE + offset) % self.data.srambytes.len()
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let outer_bank = if self.data.p_rom.len() > MMC1_PRG_OUTER_BANK_SIZE {
+            usize::from((self.chr_bank0 >> 4) & 0x01)
+        } else {
+            0
+        };
+        let inner_bank = usize::from(self.prg_bank & 0x0f);
+        let last_bank = (MMC1_PRG_OUTER_BANK_SIZE / MMC1_PRG_BANK_SIZE) - 1;
+        let is_upper = addr >= 0xc000;
+        let bank = match (self.control >> 2) & 0x03 {
+            //32k mode, the low bit of the bank number is ignored
+            0 | 1 => (inner_bank & 0x0e) | (if is_upper { 1 } else { 0 }),
+            //First bank fixed at $8000, switch $C000
+            2 => {
+                if is_upper {
+                    inner_bank
+                } else {
+                    0
+                }
+            }
+            //Switch $8000, last bank fixed at $C000
+            _ => {
+                if is_upper {
+                    last_bank
+                } else {
+                    inner_bank
+                }
+            }
+        };
+        let offset = usize::from(addr) & (MMC1_PRG_BANK_SIZE - 1);
+        let index = outer_bank * MMC1_PRG_OUTER_BANK_SIZE + bank * MMC1_PRG_BANK_SIZE + offset;
+        index % self.data.p_rom.len()
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let is_upper = addr >= 0x1000;
+        let bank = if (self.control & 0x10) == 0x10 {
+            //Two separate 4k banks
+            usize::from(if is_upper { self.chr_bank1 } else { self.chr_bank0 })
+        } else {
+            //One 8k bank, the low bit is ignored
+            usize::from(self.chr_bank0 & 0x1e) | (if is_upper { 1 } else { 0 })
+        };
+        let offset = usize::from(addr) & (MMC1_CHR_BANK_SIZE - 1);
+        bank * MMC1_CHR_BANK_SIZE + offset
+    }
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr & 0xe000 {
+            0x8000 => self.control = data,
+            0xa000 => self.chr_bank0 = data,
+            0xc000 => self.chr_bank1 = data,
+            _ => self.prg_bank = data,
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_sram_enable() {
+                self.data.srambytes[self.sram_index(addr)]
+            } else {
+                open_bus(addr)
+            }
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_sram_enable() {
+                let index = self.sram_index(addr);
+                self.data.srambytes[index] = data;
+            }
+        } else if !is_nondestructive {
+            if (data & 0x80) == 0x80 {
+                //Writing bit 7 resets the serial port and goes back to fixing the last bank
+                self.shift_reg = 0x10;
+                self.control |= 0x0c;
+            } else {
+                let is_last_write = (self.shift_reg & 0x01) == 0x01;
+                self.shift_reg = (self.shift_reg >> 1) | ((data & 0x01) << 4);
+                if is_last_write {
+                    //Only the address of the fifth write decides which register gets it
+                    let value = self.shift_reg & 0x1f;
+                    self.write_register(addr, value);
+                    self.shift_reg = 0x10;
+                }
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.control & 0x03 {
+            0 => MirrorTable::SingleScreenLower,
+            1 => MirrorTable::SingleScreenUpper,
+            2 => MirrorTable::Vertical,
+            _ => MirrorTable::Horizontal,
+        }
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.data.battery_save_data()
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.data.load_battery_save_data(data);
+    }
+}
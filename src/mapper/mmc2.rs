@@ -0,0 +1,127 @@
+/* MMC2 and MMC4, mappers 9 and 10 */
+//http://wiki.nesdev.com/w/index.php/MMC2
+//http://wiki.nesdev.com/w/index.php/MMC4
+//Each half of the pattern table has two 4k CHR banks, and which one is live flips by itself whenever the PPU fetches tile $FD or $FE.
+//Punch-Out!! (MMC2) and Fire Emblem (MMC4) use this to get more graphics on screen than 4k at a time.
+//The latch only changes after the fetch that triggers it, so that tile still comes from the old bank.
+
+use super::Mapper;
+use crate::rom::*;
+
+pub const MMC2_PRG_BANK_SIZE: usize = 0x2000;
+pub const MMC4_PRG_BANK_SIZE: usize = 0x4000;
+pub const MMC2_CHR_BANK_SIZE: usize = 0x1000;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mmc2Board {
+    //Mapper 9, one switchable 8k bank and three fixed, no RAM
+    Mmc2,
+    //Mapper 10, one switchable 16k bank and one fixed, 8k of RAM
+    Mmc4,
+}
+
+#[derive(Debug)]
+pub struct Mmc2 {
+    data: RomData,
+    board: Mmc2Board,
+    prg_bank: u8,
+    //[$0000 with latch FD, $0000 with latch FE, $1000 with latch FD, $1000 with latch FE]
+    chr_banks: [u8; 4],
+    //false for FD, true for FE. One latch per pattern table
+    latches: [bool; 2],
+    mirroring: u8,
+}
+
+impl Mmc2 {
+    pub fn new(data: RomData, board: Mmc2Board) -> Self {
+        Self {
+            data,
+            board,
+            prg_bank: 0,
+            chr_banks: [0; 4],
+            latches: [true, true],
+            mirroring: 0,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let bank_size = match self.board {
+            Mmc2Board::Mmc2 => MMC2_PRG_BANK_SIZE,
+            Mmc2Board::Mmc4 => MMC4_PRG_BANK_SIZE,
+        };
+        let num_of_banks = self.data.p_rom.len() / bank_size;
+        let slot = usize::from(addr - PRG_ROM_SYSTEM_BASE_ADDR) / bank_size;
+        //Only the first slot switches, everything after is the end of the ROM
+        let bank = if slot == 0 {
+            usize::from(self.prg_bank)
+        } else {
+            let num_of_slots = 0x8000 / bank_size;
+            num_of_banks.saturating_sub(num_of_slots - slot)
+        };
+        let offset = usize::from(addr) & (bank_size - 1);
+        (bank * bank_size + offset) % self.data.p_rom.len()
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let table = usize::from(addr >> 12) & 0x01;
+        let latch = if self.latches[table] { 1 } else { 0 };
+        let bank = usize::from(self.chr_banks[(table << 1) | latch]);
+        bank * MMC2_CHR_BANK_SIZE + (usize::from(addr) & (MMC2_CHR_BANK_SIZE - 1))
+    }
+    //MMC2 only looks at the exact address of the first row of the upper plane in the left table,
+    //MMC4 and the right table on both chips take the whole 8 bytes of the upper plane
+    fn update_latch(&mut self, addr: u16) {
+        let table = usize::from(addr >> 12) & 0x01;
+        let tile_addr = addr & 0x0ff8;
+        let is_exact = table == 1 || self.board == Mmc2Board::Mmc4 || (addr & 0x0007) == 0;
+        if !is_exact {
+            return;
+        }
+        match tile_addr {
+            0x0fd8 => self.latches[table] = false,
+            0x0fe8 => self.latches[table] = true,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+        } else if !is_nondestructive {
+            match addr & 0xf000 {
+                0xa000 => self.prg_bank = data & 0x0f,
+                0xb000 => self.chr_banks[0] = data & 0x1f,
+                0xc000 => self.chr_banks[1] = data & 0x1f,
+                0xd000 => self.chr_banks[2] = data & 0x1f,
+                0xe000 => self.chr_banks[3] = data & 0x1f,
+                0xf000 => self.mirroring = data & 0x01,
+                _ => {}
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        let data = self.data.read_chr(self.chr_index(addr));
+        self.update_latch(addr);
+        data
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        if self.mirroring == 0 {
+            MirrorTable::Vertical
+        } else {
+            MirrorTable::Horizontal
+        }
+    }
+}
@@ -0,0 +1,453 @@
+/* MMC3 and MMC6, mapper 4 */
+//http://wiki.nesdev.com/w/index.php/MMC3
+//Two switchable 8k PRG banks, 2k and 1k CHR banks, mirroring control, and the famous scanline counter.
+//SMB3, Kirby and Mega Man 3-6 all use it.
+//The counter isn't told about scanlines at all, it watches address line A12 on the PPU bus. With the background and sprites in
+//different pattern tables, A12 goes up exactly once a line (when the PPU moves from one table to the other), and the MMC3 counts those.
+//http://wiki.nesdev.com/w/index.php/MMC6
+//MMC6 is the same chip with 1k of RAM inside it and a different write protect scheme (StarTropics).
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_118
+//TKSROM/TLSROM (mapper 118) wire bit 7 of the CHR banks to the nametable select instead of mirroring control,
+//so each nametable follows whichever CHR register covers the same slot. Armadillo, Alien Syndrome.
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_119
+//TQROM (mapper 119) has CHR ROM and 8k of CHR RAM on the board at once, bit 6 of a CHR bank picks the RAM. High Speed, Pin*Bot.
+
+use super::Mapper;
+use crate::rom::*;
+use crate::video::*;
+
+pub const MMC3_PRG_BANK_SIZE: usize = 0x2000;
+pub const MMC3_CHR_BANK_SIZE: usize = 0x0400;
+pub const MMC6_SRAM_SIZE: usize = 0x0400;
+pub const TQROM_CHR_RAM_SIZE: usize = 0x2000;
+//The real chip ignores A12 going up unless it sat low for a few CPU cycles first, which filters out the
+//quick toggles in the middle of background fetches. We count pattern fetches with A12 low instead of cycles
+pub const MMC3_A12_FILTER: u8 = 2;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mmc3Board {
+    //Mapper 4
+    Mmc3,
+    //Mapper 4, submapper 1
+    Mmc6,
+    //Mapper 118
+    Txsrom,
+    //Mapper 119
+    Tqrom,
+}
+
+#[derive(Debug)]
+pub struct Mmc3 {
+    data: RomData,
+    board: Mmc3Board,
+    //$8000, which bank register $8001 writes to, plus the PRG/CHR layout bits
+    bank_select: u8,
+    //R0-R7
+    bank_regs: [u8; 8],
+    //$A000, 0 is vertical and 1 is horizontal
+    mirroring: u8,
+    //$A001, RAM enable/write protect
+    sram_protect: u8,
+    //$C000, the value the counter reloads from
+    irq_latch: u8,
+    irq_counter: u8,
+    //$C001 asks for a reload on the next clock
+    is_irq_reload: bool,
+    //$E000/$E001
+    is_irq_enable: bool,
+    is_irq: bool,
+    //How many pattern fetches in a row have had A12 low
+    a12_low_count: u8,
+}
+
+impl Mmc3 {
+    pub fn new(mut data: RomData, board: Mmc3Board) -> Self {
+        //NES 2.0 submapper 1 is MMC6
+        let board = if board == Mmc3Board::Mmc3 && data.submapper_id == 1 {
+            Mmc3Board::Mmc6
+        } else {
+            board
+        };
+        //Old TQROM headers only mention the CHR ROM
+        if board == Mmc3Board::Tqrom && data.c_ram.len() < TQROM_CHR_RAM_SIZE {
+            data.c_ram.resize(TQROM_CHR_RAM_SIZE, 0);
+        }
+        Self {
+            data,
+            board,
+            bank_select: 0,
+            bank_regs: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: 0,
+            sram_protect: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            is_irq_reload: false,
+            is_irq_enable: false,
+            is_irq: false,
+            a12_low_count: 0,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / MMC3_PRG_BANK_SIZE;
+        let second_last_bank = num_of_banks.saturating_sub(2);
+        let last_bank = num_of_banks.saturating_sub(1);
+        let is_prg_inverted = (self.bank_select & 0x40) == 0x40;
+        let bank = match ((addr >> 13) & 0x03, is_prg_inverted) {
+            //$8000 is R6, or the second to last bank when inverted
+            (0, false) => usize::from(self.bank_regs[6]),
+            (0, true) => second_last_bank,
+            //$A000 is always R7
+            (1, _) => usize::from(self.bank_regs[7]),
+            //$C000 is the opposite of $8000
+            (2, false) => second_last_bank,
+            (2, true) => usize::from(self.bank_regs[6]),
+            //$E000 is always the last bank
+            _ => last_bank,
+        };
+        let offset = usize::from(addr) & (MMC3_PRG_BANK_SIZE - 1);
+        (bank * MMC3_PRG_BANK_SIZE + offset) % self.data.p_rom.len()
+    }
+    fn is_mmc6(&self) -> bool {
+        self.board == Mmc3Board::Mmc6
+    }
+    //The CHR bank in 1k units, with all 8 bits of the register still there for the boards that use the top ones
+    fn chr_bank(&self, addr: u16) -> usize {
+        //CHR inversion swaps which half of the pattern tables gets the 2k banks
+        let inverted_addr = if (self.bank_select & 0x80) == 0x80 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+        let slot = usize::from(inverted_addr >> 10) & 0x07;
+        match slot {
+            //R0 and R1 are 2k banks, the low bit picks which half
+            0 | 1 => usize::from(self.bank_regs[0] & 0xfe) | (slot & 0x01),
+            2 | 3 => usize::from(self.bank_regs[1] & 0xfe) | (slot & 0x01),
+            //R2-R5 are 1k each
+            _ => usize::from(self.bank_regs[slot - 2]),
+        }
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let offset = usize::from(addr) & (MMC3_CHR_BANK_SIZE - 1);
+        self.chr_bank(addr) * MMC3_CHR_BANK_SIZE + offset
+    }
+    //TQROM only, bit 6 of the bank sends the access to the CHR RAM
+    fn is_chr_ram_bank(&self, addr: u16) -> bool {
+        self.board == Mmc3Board::Tqrom && (self.chr_bank(addr) & 0x40) == 0x40
+    }
+    fn chr_ram_index(&self, addr: u16) -> usize {
+        let offset = usize::from(addr) & (MMC3_CHR_BANK_SIZE - 1);
+        (self.chr_bank(addr) & 0x07) * MMC3_CHR_BANK_SIZE + offset
+    }
+    //TxSROM only, the nametable address goes through the CHR banking like a pattern table address would
+    fn nametable_page(&self, addr: u16) -> usize {
+        (self.chr_bank(addr & 0x0fff) >> 7) & 0x01
+    }
+    //Which RAM access is allowed, as (is_read, is_write)
+    fn sram_access(&self, addr: u16) -> (bool, bool) {
+        if self.is_mmc6() {
+            //MMC6 has a master enable in $8000, then read/write enables for each 512 byte half in $A001
+            if (self.bank_select & 0x20) == 0x00 {
+                return (false, false);
+            }
+            let shift = if (addr & 0x0200) == 0x0200 { 6 } else { 4 };
+            let is_read = (self.sram_protect >> shift) & 0x02 == 0x02;
+            let is_write = (self.sram_protect >> shift) & 0x01 == 0x01;
+            (is_read, is_read && is_write)
+        } else {
+            let is_enable = (self.sram_protect & 0x80) == 0x80;
+            let is_write_protect = (self.sram_protect & 0x40) == 0x40;
+            (is_enable, is_enable && !is_write_protect)
+        }
+    }
+    fn sram_index(&self, addr: u16) -> usize {
+        if self.is_mmc6() {
+            //The 1k of internal RAM sits at $7000 and shows up over and over through $7FFF, there's nothing at $6000
+            usize::from(addr) & (MMC6_SRAM_SIZE - 1)
+        } else {
+            usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR)
+        }
+    }
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.is_irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.is_irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.is_irq_enable {
+            self.is_irq = true;
+        }
+    }
+    //Every pattern table access comes through here, so this is where we watch A12
+    fn watch_a12(&mut self, addr: u16) {
+        if (addr & 0x1000) == 0x1000 {
+            if self.a12_low_count >= MMC3_A12_FILTER {
+                self.clock_irq_counter();
+            }
+            self.a12_low_count = 0;
+        } else {
+            self.a12_low_count = self.a12_low_count.saturating_add(1);
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let (is_read, _) = self.sram_access(addr);
+            if is_read && !(self.is_mmc6() && addr < 0x7000) {
+                self.data.srambytes[self.sram_index(addr)]
+            } else {
+                open_bus(addr)
+            }
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let (_, is_write) = self.sram_access(addr);
+            if is_write && !(self.is_mmc6() && addr < 0x7000) {
+                let index = self.sram_index(addr);
+                self.data.srambytes[index] = data;
+            }
+        } else if !is_nondestructive {
+            //Each 8k range has two registers, picked by whether the address is even or odd
+            let is_odd = (addr & 0x01) == 0x01;
+            match (addr & 0xe000, is_odd) {
+                (0x8000, false) => self.bank_select = data,
+                (0x8000, true) => self.bank_regs[usize::from(self.bank_select & 0x07)] = data,
+                (0xa000, false) => self.mirroring = data & 0x01,
+                (0xa000, true) => self.sram_protect = data,
+                (0xc000, false) => self.irq_latch = data,
+                (0xc000, true) => {
+                    self.irq_counter = 0;
+                    self.is_irq_reload = true;
+                }
+                (0xe000, false) => {
+                    //Disabling also acknowledges anything pending
+                    self.is_irq_enable = false;
+                    self.is_irq = false;
+                }
+                _ => self.is_irq_enable = true,
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.watch_a12(addr);
+        if self.is_chr_ram_bank(addr) {
+            self.data.read_chr_ram(self.chr_ram_index(addr))
+        } else {
+            self.data.read_chr(self.chr_index(addr))
+        }
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        self.watch_a12(addr);
+        if self.is_chr_ram_bank(addr) {
+            let index = self.chr_ram_index(addr);
+            self.data.write_chr_ram(index, data);
+        } else {
+            let index = self.chr_index(addr);
+            self.data.write_chr(index, data);
+        }
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        //Four screen boards (Rad Racer II) have the extra RAM wired in and $A000 does nothing
+        if self.data.is_alt_nametables {
+            MirrorTable::FourScreen
+        } else if self.mirroring == 0 {
+            MirrorTable::Vertical
+        } else {
+            MirrorTable::Horizontal
+        }
+    }
+    fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        if self.board == Mmc3Board::Txsrom {
+            let offset = usize::from(addr) & (NAME_TABLE_SIZE - 1);
+            nametables[self.nametable_page(addr)][offset]
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset]
+        }
+    }
+    fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        if self.board == Mmc3Board::Txsrom {
+            let offset = usize::from(addr) & (NAME_TABLE_SIZE - 1);
+            nametables[self.nametable_page(addr)][offset] = data;
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset] = data;
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.is_irq
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        let mut data = self.data.battery_save_data()?;
+        //MMC6 only has the 1k inside the chip, the rest of srambytes never gets touched
+        if self.is_mmc6() {
+            data.truncate(MMC6_SRAM_SIZE);
+        }
+        Some(data)
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.data.load_battery_save_data(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //256k PRG and 256k CHR where every byte of a bank holds that bank's number
+    fn new_mapper(board: Mmc3Board, submapper_id: u8) -> Mmc3 {
+        let p_rom = (0..32).flat_map(|bank| vec![bank as u8; MMC3_PRG_BANK_SIZE]).collect();
+        let c_rom = (0..256).flat_map(|bank| vec![bank as u8; MMC3_CHR_BANK_SIZE]).collect();
+        let data = RomData { mapper_id: 4, submapper_id, sram: true, p_rom, c_rom, ..RomData::default() };
+        Mmc3::new(data, board)
+    }
+    fn write_reg(mapper: &mut Mmc3, bank_select: u8, data: u8) {
+        mapper.write_u8(0x8000, bank_select, false);
+        mapper.write_u8(0x8001, data, false);
+    }
+    fn prg_banks(mapper: &mut Mmc3) -> [u8; 4] {
+        [0x8000, 0xa000, 0xc000, 0xe000].map(|addr| mapper.read_u8(addr, false))
+    }
+    fn chr_banks(mapper: &mut Mmc3) -> [u8; 8] {
+        let mut banks = [0; 8];
+        for (slot, bank) in banks.iter_mut().enumerate() {
+            *bank = mapper.read_video_u8((slot as u16) << 10);
+        }
+        banks
+    }
+    //What the PPU does over one rendered line with background at $0000 and sprites at $1000, A12 goes up once
+    fn render_line(mapper: &mut Mmc3) {
+        for _ in 0..32 {
+            mapper.read_video_u8(0x0000);
+            mapper.read_video_u8(0x0008);
+        }
+        for _ in 0..8 {
+            mapper.read_video_u8(0x1000);
+            mapper.read_video_u8(0x1008);
+        }
+    }
+
+    #[test]
+    fn prg_banks_and_inversion() {
+        let mut mapper = new_mapper(Mmc3Board::Mmc3, 0);
+        write_reg(&mut mapper, 6, 3);
+        write_reg(&mut mapper, 7, 5);
+        assert_eq!(prg_banks(&mut mapper), [3, 5, 30, 31]);
+        mapper.write_u8(0x8000, 0x40, false);
+        assert_eq!(prg_banks(&mut mapper), [30, 5, 3, 31]);
+    }
+
+    #[test]
+    fn chr_banks_and_inversion() {
+        let mut mapper = new_mapper(Mmc3Board::Mmc3, 0);
+        for (reg, bank) in [0x11, 0x20, 0x40, 0x41, 0x42, 0xff].iter().enumerate() {
+            write_reg(&mut mapper, reg as u8, *bank);
+        }
+        //R0 and R1 ignore the low bit
+        assert_eq!(chr_banks(&mut mapper), [0x10, 0x11, 0x20, 0x21, 0x40, 0x41, 0x42, 0xff]);
+        mapper.write_u8(0x8000, 0x80, false);
+        assert_eq!(chr_banks(&mut mapper), [0x40, 0x41, 0x42, 0xff, 0x10, 0x11, 0x20, 0x21]);
+    }
+
+    #[test]
+    fn scanline_irq() {
+        let mut mapper = new_mapper(Mmc3Board::Mmc3, 0);
+        mapper.write_u8(0xc000, 3, false);
+        mapper.write_u8(0xc001, 0, false);
+        mapper.write_u8(0xe001, 0, false);
+        //Reload to 3, then 2, 1, 0
+        for _ in 0..3 {
+            render_line(&mut mapper);
+            assert!(!mapper.is_irq());
+        }
+        render_line(&mut mapper);
+        assert!(mapper.is_irq());
+        //Stays up until $E000
+        render_line(&mut mapper);
+        assert!(mapper.is_irq());
+        mapper.write_u8(0xe000, 0, false);
+        assert!(!mapper.is_irq());
+        //The line after it hit 0 reloaded it from the latch, so three more to go
+        mapper.write_u8(0xe001, 0, false);
+        for _ in 0..2 {
+            render_line(&mut mapper);
+            assert!(!mapper.is_irq());
+        }
+        render_line(&mut mapper);
+        assert!(mapper.is_irq());
+    }
+
+    #[test]
+    fn scanline_irq_latch_zero() {
+        let mut mapper = new_mapper(Mmc3Board::Mmc3, 0);
+        mapper.write_u8(0xc000, 0, false);
+        mapper.write_u8(0xc001, 0, false);
+        mapper.write_u8(0xe001, 0, false);
+        for _ in 0..3 {
+            render_line(&mut mapper);
+            assert!(mapper.is_irq());
+            mapper.write_u8(0xe000, 0, false);
+            mapper.write_u8(0xe001, 0, false);
+        }
+    }
+
+    #[test]
+    fn a12_filter() {
+        let mut mapper = new_mapper(Mmc3Board::Mmc3, 0);
+        mapper.write_u8(0xc000, 1, false);
+        mapper.write_u8(0xc001, 0, false);
+        mapper.write_u8(0xe001, 0, false);
+        render_line(&mut mapper);
+        assert!(!mapper.is_irq());
+        //A12 only dropping for a single fetch isn't a new line
+        for _ in 0..8 {
+            mapper.read_video_u8(0x0000);
+            mapper.read_video_u8(0x1000);
+        }
+        assert!(!mapper.is_irq());
+        render_line(&mut mapper);
+        assert!(mapper.is_irq());
+    }
+
+    #[test]
+    fn mmc3_ram_protect() {
+        let mut mapper = new_mapper(Mmc3Board::Mmc3, 0);
+        mapper.write_u8(0x6000, 0x12, false);
+        assert_eq!(mapper.read_u8(0x6000, false), 0x60);
+        mapper.write_u8(0xa001, 0x80, false);
+        mapper.write_u8(0x6000, 0x12, false);
+        assert_eq!(mapper.read_u8(0x6000, false), 0x12);
+        mapper.write_u8(0xa001, 0xc0, false);
+        mapper.write_u8(0x6000, 0x34, false);
+        assert_eq!(mapper.read_u8(0x6000, false), 0x12);
+        assert_eq!(mapper.save_data().unwrap().len(), BATTERY_PACKED_RAM_MAX_SIZE);
+    }
+
+    #[test]
+    fn mmc6_ram() {
+        let mut mapper = new_mapper(Mmc3Board::Mmc3, 1);
+        mapper.write_u8(0x8000, 0x20, false);
+        //Both halves readable and writable
+        mapper.write_u8(0xa001, 0xf0, false);
+        mapper.write_u8(0x7000, 0x12, false);
+        mapper.write_u8(0x7200, 0x34, false);
+        //$7000-$73FF repeats up to $7FFF, and there's nothing at $6000
+        assert_eq!(mapper.read_u8(0x7c00, false), 0x12);
+        assert_eq!(mapper.read_u8(0x7e00, false), 0x34);
+        assert_eq!(mapper.read_u8(0x6000, false), 0x60);
+        //Write protect the upper half
+        mapper.write_u8(0xa001, 0xb0, false);
+        mapper.write_u8(0x7200, 0x56, false);
+        assert_eq!(mapper.read_u8(0x7200, false), 0x34);
+        //Only the 1k inside the chip gets saved
+        let data = mapper.save_data().unwrap();
+        assert_eq!(data.len(), MMC6_SRAM_SIZE);
+        assert_eq!((data[0x000], data[0x200]), (0x12, 0x34));
+    }
+}
@@ -0,0 +1,668 @@
+/* MMC5, mapper 5 */
+//http://wiki.nesdev.com/w/index.php/MMC5
+//Nintendo's biggest mapper. Four PRG banking modes with RAM and ROM mixed anywhere, four CHR modes with a separate set of banks for
+//the background when 8x16 sprites are on, 1k of extra RAM (ExRAM) that can be a third nametable or per-tile attributes,
+//a fill mode nametable, a vertical split screen, a scanline IRQ, a multiplier, and two extra pulse channels plus a PCM channel.
+//Castlevania III and the Koei strategy games use it.
+//The real chip works out what the PPU is doing by snooping its reads, we get told through notify_fetch_phase instead.
+
+use super::{FetchPhase, Mapper};
+use crate::rom::*;
+use crate::video::*;
+
+pub const MMC5_PRG_BANK_SIZE: usize = 0x2000;
+pub const MMC5_CHR_BANK_SIZE: usize = 0x0400;
+pub const MMC5_EXRAM_SIZE: usize = 0x0400;
+//The audio frame counter runs at a fixed 240Hz instead of following the APU
+pub const MMC5_AUDIO_FRAME_CYCLE: u16 = 7457;
+
+//https://wiki.nesdev.com/w/index.php/APU_Length_Counter
+const LENGTH_TABLE: [u8; 0x20] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+//https://wiki.nesdev.com/w/index.php/APU_Pulse
+const DUTY_TABLE: [u8; 4] = [0b0100_0000, 0b0110_0000, 0b0111_1000, 0b1001_1111];
+
+//Same as the APU's pulse channels minus the sweep unit
+#[derive(Clone, Debug, Default)]
+pub struct Mmc5Pulse {
+    //$5000/$5004
+    control: u8,
+    //$5002-$5003/$5006-$5007, 11 bits
+    timer_period: u16,
+    timer: u16,
+    duty_step: u8,
+    length_counter: u8,
+    is_enable: bool,
+    is_envelope_start: bool,
+    envelope_divider: u8,
+    envelope_decay: u8,
+}
+
+impl Mmc5Pulse {
+    fn write_reg(&mut self, index: u16, data: u8) {
+        match index {
+            0 => self.control = data,
+            2 => self.timer_period = (self.timer_period & 0x0700) | u16::from(data),
+            3 => {
+                self.timer_period = (self.timer_period & 0x00ff) | (u16::from(data & 0x07) << 8);
+                if self.is_enable {
+                    self.length_counter = LENGTH_TABLE[usize::from(data >> 3)];
+                }
+                self.duty_step = 0;
+                self.is_envelope_start = true;
+            }
+            _ => {}
+        }
+    }
+    fn set_enable(&mut self, is_enable: bool) {
+        self.is_enable = is_enable;
+        if !is_enable {
+            self.length_counter = 0;
+        }
+    }
+    //Every other CPU cycle
+    fn step_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.duty_step = (self.duty_step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+    fn step_frame(&mut self) {
+        let is_loop = (self.control & 0x20) == 0x20;
+        //Envelope
+        if self.is_envelope_start {
+            self.is_envelope_start = false;
+            self.envelope_decay = 15;
+            self.envelope_divider = self.control & 0x0f;
+        } else if self.envelope_divider == 0 {
+            self.envelope_divider = self.control & 0x0f;
+            if self.envelope_decay > 0 {
+                self.envelope_decay -= 1;
+            } else if is_loop {
+                self.envelope_decay = 15;
+            }
+        } else {
+            self.envelope_divider -= 1;
+        }
+        //Length counter, the loop flag doubles as halt
+        if !is_loop && self.length_counter > 0 {
+            self.length_counter -= 1;
+        }
+    }
+    fn output(&self) -> u8 {
+        let duty = DUTY_TABLE[usize::from(self.control >> 6)];
+        let is_high = (duty >> (7 - self.duty_step)) & 0x01 == 0x01;
+        if self.length_counter == 0 || self.timer_period < 8 || !is_high {
+            0
+        } else if (self.control & 0x10) == 0x10 {
+            self.control & 0x0f
+        } else {
+            self.envelope_decay
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Mmc5 {
+    data: RomData,
+    //$5100-$5101
+    prg_mode: u8,
+    chr_mode: u8,
+    //$5102-$5103, both have to hold the magic values before RAM can be written
+    sram_protect: [u8; 2],
+    //$5104
+    exram_mode: u8,
+    //$5105, two bits per nametable
+    nametable_mapping: u8,
+    //$5106-$5107
+    fill_tile: u8,
+    fill_attribute: u8,
+    //$5113-$5117
+    prg_banks: [u8; 5],
+    //$5120-$512B, with 8x16 sprites the sprites use the first 8 and the background the last 4
+    chr_banks: [u16; 12],
+    //$5130, upper bits for the CHR banks
+    chr_upper: u8,
+    //In 8x8 mode whichever set got written last is used for everything
+    is_last_chr_write_bg: bool,
+    //$5200-$5202
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    //$5203-$5204
+    irq_compare: u8,
+    is_irq_enable: bool,
+    is_irq_pending: bool,
+    is_in_frame: bool,
+    scanline_counter: u8,
+    //$5205-$5206
+    multiplicand: u8,
+    multiplier: u8,
+    exram: [u8; MMC5_EXRAM_SIZE],
+
+    //What the PPU is up to, and where in the line it is
+    fetch_phase: FetchPhase,
+    current_line: u16,
+    is_line_fetched: bool,
+    tile_column: u8,
+    //Per tile state for extended attributes and the split, picked up on the nametable fetch
+    ext_attribute: u8,
+    is_split_tile: bool,
+    split_tile_y: u16,
+
+    //Audio
+    pulses: [Mmc5Pulse; 2],
+    //$5010-$5011
+    pcm_control: u8,
+    pcm_level: u8,
+    is_pcm_irq: bool,
+    audio_frame_counter: u16,
+    is_odd_cycle: bool,
+}
+
+impl Mmc5 {
+    pub fn new(data: RomData) -> Self {
+        Self {
+            data,
+            prg_mode: 3,
+            chr_mode: 0,
+            sram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xff],
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            is_last_chr_write_bg: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            is_irq_enable: false,
+            is_irq_pending: false,
+            is_in_frame: false,
+            scanline_counter: 0,
+            multiplicand: 0xff,
+            multiplier: 0xff,
+            exram: [0; MMC5_EXRAM_SIZE],
+            fetch_phase: FetchPhase::Idle,
+            current_line: 0,
+            is_line_fetched: false,
+            tile_column: 0,
+            ext_attribute: 0,
+            is_split_tile: false,
+            split_tile_y: 0,
+            pulses: [Mmc5Pulse::default(), Mmc5Pulse::default()],
+            pcm_control: 0,
+            pcm_level: 0,
+            is_pcm_irq: false,
+            audio_frame_counter: 0,
+            is_odd_cycle: false,
+        }
+    }
+    //Works out which bank register covers a CPU address, and whether it points at RAM
+    fn prg_bank(&self, addr: u16) -> (bool, usize) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            return (true, usize::from(self.prg_banks[0] & 0x07));
+        }
+        let slot = usize::from((addr - PRG_ROM_SYSTEM_BASE_ADDR) >> 13);
+        //(register, how many 8k banks the window covers)
+        let (reg, size) = match (self.prg_mode & 0x03, slot) {
+            (0, _) => (4, 4),
+            (1, 0) | (1, 1) => (2, 2),
+            (1, _) => (4, 2),
+            (2, 0) | (2, 1) => (2, 2),
+            (2, 2) => (3, 1),
+            (2, _) => (4, 1),
+            (_, _) => (slot + 1, 1),
+        };
+        let value = self.prg_banks[reg];
+        //$5117 is always ROM, the others pick with bit 7
+        let is_ram = reg != 4 && (value & 0x80) == 0x00;
+        let base = usize::from(value & 0x7f) & !(size - 1);
+        let bank = base + (slot & (size - 1));
+        if is_ram {
+            (true, bank & 0x07)
+        } else {
+            (false, bank)
+        }
+    }
+    fn is_sram_writable(&self) -> bool {
+        self.sram_protect[0] == 0x02 && self.sram_protect[1] == 0x01
+    }
+    fn sram_index(&self, bank: usize, addr: u16) -> usize {
+        let offset = usize::from(addr) & (MMC5_PRG_BANK_SIZE - 1);
+        (bank * MMC5_PRG_BANK_SIZE + offset) % self.data.srambytes.len()
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let (is_split, is_ext) = match self.fetch_phase {
+            FetchPhase::Background { .. } => (self.is_split_tile, self.exram_mode == 1),
+            _ => (false, false),
+        };
+        if is_split {
+            //The split has its own 4k bank and its own fine scroll
+            let offset = usize::from(addr & 0x0ff8) | usize::from(self.split_tile_y & 0x07);
+            return usize::from(self.split_bank) * 0x1000 + offset;
+        }
+        if is_ext {
+            //Extended attributes give every tile its own 4k bank
+            let bank = (usize::from(self.chr_upper & 0x03) << 6) | usize::from(self.ext_attribute & 0x3f);
+            return bank * 0x1000 + (usize::from(addr) & 0x0fff);
+        }
+        //The sets only split between background and sprites with 8x16 sprites, otherwise the last written set is used for everything
+        let is_bg_set = match self.fetch_phase {
+            FetchPhase::Background { is_large_sprite: true } => true,
+            FetchPhase::Sprite { is_large_sprite: true } => false,
+            _ => self.is_last_chr_write_bg,
+        };
+        let slot = usize::from(addr >> 10) & 0x07;
+        //(register, how many 1k banks the window covers)
+        let (reg, size): (usize, usize) = if is_bg_set {
+            //The background set only covers 4k, and shows up in both pattern tables
+            match self.chr_mode & 0x03 {
+                0 => (11, 8),
+                1 => (11, 4),
+                2 => (9 + ((slot >> 1) & 0x01) * 2, 2),
+                _ => (8 + (slot & 0x03), 1),
+            }
+        } else {
+            match self.chr_mode & 0x03 {
+                0 => (7, 8),
+                1 => (3 + (slot & 0x04), 4),
+                2 => ((slot | 0x01), 2),
+                _ => (slot, 1),
+            }
+        };
+        //The registers count in the mode's own page size, not 1k
+        let bank = (usize::from(self.chr_banks[reg]) << size.trailing_zeros()) + (slot & (size - 1));
+        bank * MMC5_CHR_BANK_SIZE + (usize::from(addr) & (MMC5_CHR_BANK_SIZE - 1))
+    }
+    fn step_scanline_irq(&mut self) {
+        if !self.is_in_frame {
+            self.is_in_frame = true;
+            self.scanline_counter = 0;
+            self.is_irq_pending = false;
+        } else {
+            self.scanline_counter = self.scanline_counter.wrapping_add(1);
+            if self.scanline_counter == self.irq_compare {
+                self.is_irq_pending = true;
+            }
+        }
+    }
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5007 => {
+                let channel = usize::from((addr >> 2) & 0x01);
+                self.pulses[channel].write_reg(addr & 0x03, data);
+            }
+            0x5010 => self.pcm_control = data,
+            //Write mode only, and a zero is ignored
+            0x5011 if (self.pcm_control & 0x01) == 0x00 && data != 0 => self.pcm_level = data,
+            0x5015 => {
+                self.pulses[0].set_enable((data & 0x01) == 0x01);
+                self.pulses[1].set_enable((data & 0x02) == 0x02);
+            }
+            0x5100 => self.prg_mode = data & 0x03,
+            0x5101 => self.chr_mode = data & 0x03,
+            0x5102 => self.sram_protect[0] = data & 0x03,
+            0x5103 => self.sram_protect[1] = data & 0x03,
+            0x5104 => self.exram_mode = data & 0x03,
+            0x5105 => self.nametable_mapping = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attribute = data & 0x03,
+            0x5113..=0x5117 => self.prg_banks[usize::from(addr - 0x5113)] = data,
+            0x5120..=0x512b => {
+                let index = usize::from(addr - 0x5120);
+                self.chr_banks[index] = u16::from(data) | (u16::from(self.chr_upper & 0x03) << 8);
+                self.is_last_chr_write_bg = index >= 8;
+            }
+            0x5130 => self.chr_upper = data & 0x03,
+            0x5200 => self.split_control = data,
+            0x5201 => self.split_scroll = data,
+            0x5202 => self.split_bank = data,
+            0x5203 => self.irq_compare = data,
+            0x5204 => self.is_irq_enable = (data & 0x80) == 0x80,
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            0x5c00..=0x5fff => {
+                let index = usize::from(addr - 0x5c00);
+                self.exram[index] = match self.exram_mode {
+                    //As a nametable ExRAM only takes writes while the PPU is drawing
+                    0 | 1 => {
+                        if self.is_in_frame {
+                            data
+                        } else {
+                            0
+                        }
+                    }
+                    2 => data,
+                    _ => self.exram[index],
+                };
+            }
+            _ => {}
+        }
+    }
+    fn read_register(&mut self, addr: u16, is_nondestructive: bool) -> u8 {
+        match addr {
+            0x5010 => {
+                let data = if self.is_pcm_irq { 0x80 } else { 0x00 } | (self.pcm_control & 0x01);
+                if !is_nondestructive {
+                    self.is_pcm_irq = false;
+                }
+                data
+            }
+            0x5015 => {
+                let mut data = 0;
+                if self.pulses[0].length_counter > 0 {
+                    data |= 0x01;
+                }
+                if self.pulses[1].length_counter > 0 {
+                    data |= 0x02;
+                }
+                data
+            }
+            0x5204 => {
+                let mut data = 0;
+                if self.is_irq_pending {
+                    data |= 0x80;
+                }
+                if self.is_in_frame {
+                    data |= 0x40;
+                }
+                if !is_nondestructive {
+                    self.is_irq_pending = false;
+                }
+                data
+            }
+            0x5205 => ((u16::from(self.multiplicand) * u16::from(self.multiplier)) & 0xff) as u8,
+            0x5206 => ((u16::from(self.multiplicand) * u16::from(self.multiplier)) >> 8) as u8,
+            0x5c00..=0x5fff => {
+                if self.exram_mode >= 2 {
+                    self.exram[usize::from(addr - 0x5c00)]
+                } else {
+                    open_bus(addr)
+                }
+            }
+            _ => open_bus(addr),
+        }
+    }
+    //The split replaces the nametable for the columns on its side of the threshold
+    fn is_split_column(&self, column: u8) -> bool {
+        let threshold = self.split_control & 0x1f;
+        let is_right = (self.split_control & 0x40) == 0x40;
+        (self.split_control & 0x80) == 0x80
+            && self.exram_mode <= 1
+            && (if is_right { column >= threshold } else { column < threshold })
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn read_u8(&mut self, addr: u16, is_nondestructive: bool) -> u8 {
+        if addr < BATTERY_PACKED_RAM_BASE_ADDR {
+            return self.read_register(addr, is_nondestructive);
+        }
+        let (is_ram, bank) = self.prg_bank(addr);
+        if is_ram {
+            self.data.srambytes[self.sram_index(bank, addr)]
+        } else {
+            let offset = usize::from(addr) & (MMC5_PRG_BANK_SIZE - 1);
+            let data = self.data.p_rom[(bank * MMC5_PRG_BANK_SIZE + offset) % self.data.p_rom.len()];
+            //PCM read mode grabs whatever the CPU reads out of $8000-$BFFF
+            if !is_nondestructive && (self.pcm_control & 0x01) == 0x01 && addr < 0xc000 {
+                if data == 0 {
+                    self.is_pcm_irq = (self.pcm_control & 0x80) == 0x80;
+                } else {
+                    self.pcm_level = data;
+                }
+            }
+            data
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        if addr < BATTERY_PACKED_RAM_BASE_ADDR {
+            self.write_register(addr, data);
+            return;
+        }
+        let (is_ram, bank) = self.prg_bank(addr);
+        if is_ram && self.is_sram_writable() {
+            let index = self.sram_index(bank, addr);
+            self.data.srambytes[index] = data;
+        }
+    }
+    //The control registers, ExRAM and the multiplier all live at $5000-$5FFF
+    fn is_expansion_area(&self) -> bool {
+        true
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        //Only used if something asks, the real layout comes from read_nametable_u8 below
+        MirrorTable::Vertical
+    }
+    fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        let offset = usize::from(addr) & (NAME_TABLE_SIZE - 1);
+        let is_attribute = offset >= usize::from(ATTRIBUTE_TABLE_OFFSET);
+        if let FetchPhase::Background { .. } = self.fetch_phase {
+            if !is_attribute {
+                //Nametable fetch, this starts a new tile
+                let column = self.tile_column;
+                self.tile_column = self.tile_column.wrapping_add(1);
+                self.is_split_tile = self.is_split_column(column);
+                if self.is_split_tile {
+                    let split_y = (u16::from(self.split_scroll) + self.current_line) % 240;
+                    self.split_tile_y = split_y;
+                    let tile_index = usize::from(split_y >> 3) * 32 + usize::from(column & 0x1f);
+                    return self.exram[tile_index];
+                }
+                self.ext_attribute = self.exram[offset];
+            } else if self.is_split_tile {
+                let column = usize::from(self.tile_column.wrapping_sub(1) & 0x1f);
+                let row = usize::from(self.split_tile_y >> 3);
+                let attribute = self.exram[0x3c0 + (row >> 2) * 8 + (column >> 2)];
+                let shift = ((row & 0x02) << 1) | (column & 0x02);
+                let palette = (attribute >> shift) & 0x03;
+                return palette * 0x55;
+            } else if self.exram_mode == 1 {
+                //Extended attributes, the palette came with the nametable fetch. Copy it into all four corners
+                return (self.ext_attribute >> 6) * 0x55;
+            }
+        }
+        let table = usize::from((addr >> 10) & 0x03);
+        match (self.nametable_mapping >> (table * 2)) & 0x03 {
+            0 => nametables[0][offset],
+            1 => nametables[1][offset],
+            2 => {
+                if self.exram_mode <= 1 {
+                    self.exram[offset]
+                } else {
+                    0
+                }
+            }
+            _ => {
+                if is_attribute {
+                    self.fill_attribute * 0x55
+                } else {
+                    self.fill_tile
+                }
+            }
+        }
+    }
+    fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        let offset = usize::from(addr) & (NAME_TABLE_SIZE - 1);
+        let table = usize::from((addr >> 10) & 0x03);
+        match (self.nametable_mapping >> (table * 2)) & 0x03 {
+            0 => nametables[0][offset] = data,
+            1 => nametables[1][offset] = data,
+            2 if self.exram_mode <= 1 => self.exram[offset] = data,
+            _ => {}
+        }
+    }
+    fn notify_fetch_phase(&mut self, phase: FetchPhase) {
+        if let FetchPhase::Background { .. } = phase {
+            self.tile_column = 0;
+            self.is_split_tile = false;
+            //The pre-render line fetches too, but the counter only cares about the visible ones
+            if self.current_line < 240 {
+                self.is_line_fetched = true;
+                self.step_scanline_irq();
+            }
+        }
+        self.fetch_phase = phase;
+    }
+    fn step_scanline(&mut self, line: u16) {
+        //No fetches on the last line means the PPU stopped drawing (vblank or rendering off)
+        if !self.is_line_fetched {
+            self.is_in_frame = false;
+        }
+        self.is_line_fetched = false;
+        self.current_line = line;
+    }
+    fn is_irq(&self) -> bool {
+        (self.is_irq_pending && self.is_irq_enable) || self.is_pcm_irq
+    }
+    fn step_cpu_cycle(&mut self) {
+        if self.is_odd_cycle {
+            self.pulses[0].step_timer();
+            self.pulses[1].step_timer();
+        }
+        self.is_odd_cycle = !self.is_odd_cycle;
+        self.audio_frame_counter += 1;
+        if self.audio_frame_counter >= MMC5_AUDIO_FRAME_CYCLE {
+            self.audio_frame_counter = 0;
+            self.pulses[0].step_frame();
+            self.pulses[1].step_frame();
+        }
+    }
+    fn audio_output(&self) -> f32 {
+        //https://wiki.nesdev.com/w/index.php/APU_Mixer, the linear approximation
+        let pulse = f32::from(self.pulses[0].output() + self.pulses[1].output());
+        0.00752 * pulse + 0.00335 * f32::from(self.pcm_level >> 1)
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.data.battery_save_data()
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.data.load_battery_save_data(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //256k of CHR where every byte of a 1k bank holds that bank's number
+    fn new_mapper(chr_mode: u8) -> Mmc5 {
+        let c_rom = (0..256).flat_map(|bank| vec![bank as u8; MMC5_CHR_BANK_SIZE]).collect();
+        let mut mapper = Mmc5::new(RomData { mapper_id: 5, c_rom, ..RomData::default() });
+        mapper.write_u8(0x5101, chr_mode, false);
+        mapper
+    }
+    fn write_chr_banks(mapper: &mut Mmc5, base: u16, banks: &[u8]) {
+        for (i, &bank) in banks.iter().enumerate() {
+            mapper.write_u8(base + i as u16, bank, false);
+        }
+    }
+    //With 8x16 sprites on, sprite fetches use the A set and background fetches the B set
+    fn chr_banks(mapper: &mut Mmc5, phase: FetchPhase) -> [u8; 8] {
+        mapper.notify_fetch_phase(phase);
+        let mut banks = [0; 8];
+        for (slot, bank) in banks.iter_mut().enumerate() {
+            *bank = mapper.read_video_u8((slot as u16) << 10);
+        }
+        banks
+    }
+    fn chr_banks_a(mapper: &mut Mmc5) -> [u8; 8] {
+        chr_banks(mapper, FetchPhase::Sprite { is_large_sprite: true })
+    }
+    fn chr_banks_b(mapper: &mut Mmc5) -> [u8; 8] {
+        chr_banks(mapper, FetchPhase::Background { is_large_sprite: true })
+    }
+
+    #[test]
+    fn chr_8x8_last_written_set() {
+        let mut mapper = new_mapper(3);
+        write_chr_banks(&mut mapper, 0x5120, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        write_chr_banks(&mut mapper, 0x5128, &[9, 10, 11, 12]);
+        let bg = FetchPhase::Background { is_large_sprite: false };
+        let sprite = FetchPhase::Sprite { is_large_sprite: false };
+        assert_eq!(chr_banks(&mut mapper, bg), [9, 10, 11, 12, 9, 10, 11, 12]);
+        assert_eq!(chr_banks(&mut mapper, sprite), [9, 10, 11, 12, 9, 10, 11, 12]);
+        mapper.write_u8(0x5127, 13, false);
+        assert_eq!(chr_banks(&mut mapper, bg), [1, 2, 3, 4, 5, 6, 7, 13]);
+        assert_eq!(chr_banks(&mut mapper, sprite), [1, 2, 3, 4, 5, 6, 7, 13]);
+    }
+
+    #[test]
+    fn chr_mode0_a() {
+        let mut mapper = new_mapper(0);
+        write_chr_banks(&mut mapper, 0x5120, &[1, 2, 3, 4, 5, 6, 7, 3]);
+        assert_eq!(chr_banks_a(&mut mapper), [24, 25, 26, 27, 28, 29, 30, 31]);
+    }
+
+    #[test]
+    fn chr_mode1_a() {
+        let mut mapper = new_mapper(1);
+        write_chr_banks(&mut mapper, 0x5120, &[1, 2, 3, 5, 6, 7, 8, 2]);
+        assert_eq!(chr_banks_a(&mut mapper), [20, 21, 22, 23, 8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn chr_mode2_a() {
+        let mut mapper = new_mapper(2);
+        write_chr_banks(&mut mapper, 0x5120, &[0, 1, 0, 4, 0, 7, 0, 10]);
+        assert_eq!(chr_banks_a(&mut mapper), [2, 3, 8, 9, 14, 15, 20, 21]);
+    }
+
+    #[test]
+    fn chr_mode3_a() {
+        let mut mapper = new_mapper(3);
+        write_chr_banks(&mut mapper, 0x5120, &[0x10, 0x21, 0x32, 0x43, 0x54, 0x65, 0x76, 0xff]);
+        assert_eq!(chr_banks_a(&mut mapper), [0x10, 0x21, 0x32, 0x43, 0x54, 0x65, 0x76, 0xff]);
+    }
+
+    #[test]
+    fn chr_mode0_b() {
+        let mut mapper = new_mapper(0);
+        write_chr_banks(&mut mapper, 0x5120, &[0, 0, 0, 0, 0, 0, 0, 5]);
+        write_chr_banks(&mut mapper, 0x5128, &[9, 9, 9, 2]);
+        assert_eq!(chr_banks_b(&mut mapper), [16, 17, 18, 19, 20, 21, 22, 23]);
+    }
+
+    #[test]
+    fn chr_mode1_b() {
+        let mut mapper = new_mapper(1);
+        write_chr_banks(&mut mapper, 0x5120, &[0, 0, 0, 6, 0, 0, 0, 7]);
+        write_chr_banks(&mut mapper, 0x5128, &[9, 9, 9, 3]);
+        //The B set only covers 4k and shows up in both pattern tables
+        assert_eq!(chr_banks_b(&mut mapper), [12, 13, 14, 15, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn chr_mode2_b() {
+        let mut mapper = new_mapper(2);
+        write_chr_banks(&mut mapper, 0x5120, &[0, 20, 0, 21, 0, 22, 0, 23]);
+        write_chr_banks(&mut mapper, 0x5128, &[9, 2, 9, 5]);
+        assert_eq!(chr_banks_b(&mut mapper), [4, 5, 10, 11, 4, 5, 10, 11]);
+    }
+
+    #[test]
+    fn chr_mode3_b() {
+        let mut mapper = new_mapper(3);
+        write_chr_banks(&mut mapper, 0x5120, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        write_chr_banks(&mut mapper, 0x5128, &[0x80, 0x91, 0xa2, 0xb3]);
+        assert_eq!(chr_banks_b(&mut mapper), [0x80, 0x91, 0xa2, 0xb3, 0x80, 0x91, 0xa2, 0xb3]);
+    }
+}
@@ -0,0 +1,151 @@
+/* Cartridge boards, one module per mapper */
+//http://wiki.nesdev.com/w/index.php/Mapper
+//Every game cartridge wires the CPU and PPU buses to its ROM/RAM chips a little differently, and the bigger games
+//add bank switching registers, IRQ counters, and so on. Each of those boards is a "mapper", and lives in its own module here.
+//The rest of the emulator only ever talks to a board through the Mapper trait below.
+
+use super::rom::*;
+use super::video::*;
+
+pub mod nrom;
+pub mod mmc1;
+pub mod uxrom;
+pub mod cnrom;
+pub mod mmc3;
+pub mod axrom;
+pub mod mmc2;
+pub mod bank;
+pub mod discrete;
+pub mod camerica;
+pub mod namco108;
+pub mod rambo1;
+pub mod eeprom;
+pub mod bandai;
+pub mod flash;
+pub mod unrom512;
+pub mod action53;
+pub mod gtrom;
+pub mod jycompany;
+pub mod ss88006;
+pub mod sunsoft3;
+pub mod sunsoft4;
+pub mod mmc5;
+pub mod vrc_irq;
+pub mod vrc4;
+pub mod vrc6;
+pub mod opll;
+pub mod vrc7;
+pub mod namco163;
+pub mod fme7;
+
+pub trait Mapper: std::fmt::Debug {
+    //CPU side of the cartridge, everything from $4020 to $FFFF
+    fn read_u8(&mut self, addr: u16, is_nondestructive: bool) -> u8;
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool);
+    //Most boards leave $4020-$5FFF alone, Rom turns that into open bus and the two above only ever see $6000 and up.
+    //Boards with registers or RAM down there override this
+    fn is_expansion_area(&self) -> bool {
+        false
+    }
+    //PPU side of the cartridge, the pattern tables at $0000-$1FFF
+    fn read_video_u8(&mut self, addr: u16) -> u8;
+    fn write_video_u8(&mut self, addr: u16, data: u8);
+    //Boards with mirroring control can change this whenever they like
+    fn mirror_table(&self) -> MirrorTable;
+    //Nametable side of the PPU bus, $2000-$2FFF. Most boards just pick a layout of the console's 2k of nametable RAM,
+    //but the address lines go through the cartridge, so fancier boards can put whatever they want there
+    fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        let (index, offset) = convert_name_table_addr(self.mirror_table(), addr);
+        nametables[index][offset]
+    }
+    fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        let (index, offset) = convert_name_table_addr(self.mirror_table(), addr);
+        nametables[index][offset] = data;
+    }
+    //The PPU tells us what it's about to fetch, real chips work this out by counting PPU reads
+    fn notify_fetch_phase(&mut self, _phase: FetchPhase) {}
+    //The cartridge can pull the CPU's IRQ line low, boards with a counter override this
+    fn is_irq(&self) -> bool {
+        false
+    }
+    //Called once for every CPU cycle that goes by
+    fn step_cpu_cycle(&mut self) {}
+    //Called every time the PPU moves on to the next line
+    fn step_scanline(&mut self, _line: u16) {}
+    //Expansion audio, the level coming out of the cartridge on the same scale as the console's own channels
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+    //Battery backed memory, handed out so the browser can keep it between sessions and hand it back on the next load.
+    //None means there's nothing on the board worth keeping
+    fn save_data(&self) -> Option<Vec<u8>> {
+        None
+    }
+    fn load_save_data(&mut self, _data: &[u8]) {}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FetchPhase {
+    //Background tiles for the current line, one tile is a nametable, attribute, and two pattern reads in that order
+    Background { is_large_sprite: bool },
+    //The 8 sprite slots for the current line, two pattern reads each
+    Sprite { is_large_sprite: bool },
+    //Not rendering, anything that shows up now came from the CPU through $2007
+    Idle,
+}
+
+//Picks the board for a mapper number, None if we don't know about it
+pub fn from_rom_data(data: RomData) -> Option<Box<dyn Mapper>> {
+    match data.mapper_id {
+        0 => Some(Box::new(nrom::Nrom::new(data))),
+        1 => Some(Box::new(mmc1::Mmc1::new(data))),
+        2 => Some(Box::new(uxrom::Uxrom::new(data, uxrom::UxromBoard::Unrom))),
+        3 => Some(Box::new(cnrom::Cnrom::new(data, cnrom::CnromBoard::Cnrom))),
+        4 => Some(Box::new(mmc3::Mmc3::new(data, mmc3::Mmc3Board::Mmc3))),
+        5 => Some(Box::new(mmc5::Mmc5::new(data))),
+        7 => Some(Box::new(axrom::Axrom::new(data))),
+        9 => Some(Box::new(mmc2::Mmc2::new(data, mmc2::Mmc2Board::Mmc2))),
+        10 => Some(Box::new(mmc2::Mmc2::new(data, mmc2::Mmc2Board::Mmc4))),
+        11 => Some(Box::new(discrete::Discrete::new(data, discrete::DiscreteBoard::ColorDreams))),
+        13 => Some(Box::new(discrete::Discrete::new(data, discrete::DiscreteBoard::Cprom))),
+        16 => Some(Box::new(bandai::Bandai::from_mapper_16(data))),
+        18 => Some(Box::new(ss88006::Ss88006::new(data))),
+        19 | 210 => Some(Box::new(namco163::Namco163::new(data))),
+        21 | 22 | 23 | 25 => Some(Box::new(vrc4::Vrc4::new(data))),
+        24 | 26 => Some(Box::new(vrc6::Vrc6::new(data))),
+        28 => Some(Box::new(action53::Action53::new(data))),
+        30 => Some(Box::new(unrom512::Unrom512::new(data))),
+        34 => {
+            let board = discrete::DiscreteBoard::from_mapper_34(&data);
+            Some(Box::new(discrete::Discrete::new(data, board)))
+        }
+        64 => Some(Box::new(rambo1::Rambo1::new(data))),
+        66 => Some(Box::new(discrete::Discrete::new(data, discrete::DiscreteBoard::Gxrom))),
+        67 => Some(Box::new(sunsoft3::Sunsoft3::new(data))),
+        68 => Some(Box::new(sunsoft4::Sunsoft4::new(data))),
+        69 => Some(Box::new(fme7::Fme7::new(data))),
+        71 => Some(Box::new(camerica::Camerica::new(data, camerica::CamericaBoard::Bf9093))),
+        76 => Some(Box::new(namco108::Namco108::new(data, namco108::Namco108Board::Namcot3446))),
+        85 => Some(Box::new(vrc7::Vrc7::new(data))),
+        88 => Some(Box::new(namco108::Namco108::new(data, namco108::Namco108Board::Namcot3433))),
+        90 => Some(Box::new(jycompany::JyCompany::new(data, jycompany::JyCompanyBoard::Jy90))),
+        94 => Some(Box::new(uxrom::Uxrom::new(data, uxrom::UxromBoard::Un1rom))),
+        95 => Some(Box::new(namco108::Namco108::new(data, namco108::Namco108Board::Namcot3425))),
+        111 => Some(Box::new(gtrom::Gtrom::new(data))),
+        118 => Some(Box::new(mmc3::Mmc3::new(data, mmc3::Mmc3Board::Txsrom))),
+        119 => Some(Box::new(mmc3::Mmc3::new(data, mmc3::Mmc3Board::Tqrom))),
+        153 => Some(Box::new(bandai::Bandai::new(data, bandai::BandaiBoard::Lz93d50Sram, None))),
+        154 => Some(Box::new(namco108::Namco108::new(data, namco108::Namco108Board::Namcot3453))),
+        159 => Some(Box::new(bandai::Bandai::new(data, bandai::BandaiBoard::Lz93d50, Some(eeprom::EepromChip::X24C01)))),
+        180 => Some(Box::new(uxrom::Uxrom::new(data, uxrom::UxromBoard::UnromFixedFirst))),
+        185 => {
+            let submapper_id = data.submapper_id;
+            Some(Box::new(cnrom::Cnrom::new(data, cnrom::CnromBoard::ChrDisable { submapper_id })))
+        }
+        206 => Some(Box::new(namco108::Namco108::new(data, namco108::Namco108Board::Namco108))),
+        209 => Some(Box::new(jycompany::JyCompany::new(data, jycompany::JyCompanyBoard::Jy209))),
+        211 => Some(Box::new(jycompany::JyCompany::new(data, jycompany::JyCompanyBoard::Jy211))),
+        232 => Some(Box::new(camerica::Camerica::new(data, camerica::CamericaBoard::Bf9096))),
+        _ => None,
+    }
+}
@@ -0,0 +1,260 @@
+/* Namco 108 family, mappers 206, 88, 154, 76 and 95 */
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_206
+//The chip the MMC3 was copied from. Same bank select/bank data pair at $8000/$8001 and the same R0-R7 layout, but
+//no PRG/CHR inversion, no IRQ, no mirroring control and only 6 bits of CHR bank and 4 of PRG. Gauntlet, Dragon Spirit, Pac-Mania.
+//A few Namco boards rewire the outputs for more CHR or to get mirroring control back:
+//  88 (NAMCOT-3433): CHR A16 comes from PPU A12, so the 2k banks sit in the first 64k of CHR and the 1k banks in the second
+//  154 (NAMCOT-3453): 88 plus single screen mirroring from bit 6 of any write
+//  76 (NAMCOT-3446): R2-R5 become 2k banks covering all of CHR, R0 and R1 do nothing
+//  95 (NAMCOT-3425): bit 5 of R0 and R1 drives the nametable select instead of a CHR line. Dragon Buster
+
+use super::bank::bank_index;
+use super::Mapper;
+use crate::rom::*;
+use crate::video::*;
+
+pub const NAMCO108_PRG_BANK_SIZE: usize = 0x2000;
+pub const NAMCO108_CHR_BANK_SIZE: usize = 0x0400;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Namco108Board {
+    //Mapper 206
+    Namco108,
+    //Mapper 88
+    Namcot3433,
+    //Mapper 154
+    Namcot3453,
+    //Mapper 76
+    Namcot3446,
+    //Mapper 95
+    Namcot3425,
+}
+
+#[derive(Debug)]
+pub struct Namco108 {
+    data: RomData,
+    board: Namco108Board,
+    //$8000, which register $8001 writes to
+    bank_select: u8,
+    //R0-R7
+    bank_regs: [u8; 8],
+    //154 only, bit 6 of the last write. Picks the upper screen when set
+    is_upper_screen: bool,
+}
+
+impl Namco108 {
+    pub fn new(data: RomData, board: Namco108Board) -> Self {
+        Self {
+            data,
+            board,
+            bank_select: 0,
+            bank_regs: [0, 2, 4, 5, 6, 7, 0, 1],
+            is_upper_screen: false,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / NAMCO108_PRG_BANK_SIZE;
+        let bank = match (addr >> 13) & 0x03 {
+            0 => usize::from(self.bank_regs[6] & 0x0f),
+            1 => usize::from(self.bank_regs[7] & 0x0f),
+            //$C000-$FFFF is always the last 16k
+            2 => num_of_banks.saturating_sub(2),
+            _ => num_of_banks.saturating_sub(1),
+        };
+        bank_index(bank, NAMCO108_PRG_BANK_SIZE, addr, self.data.p_rom.len())
+    }
+    //The CHR bank in 1k units for a pattern table address
+    fn chr_bank(&self, addr: u16) -> usize {
+        let slot = usize::from(addr >> 10) & 0x07;
+        if self.board == Namco108Board::Namcot3446 {
+            //Four 2k banks from R2-R5
+            let reg = usize::from(self.bank_regs[2 + (slot >> 1)] & 0x3f);
+            return (reg << 1) | (slot & 0x01);
+        }
+        let bank = match slot {
+            //R0 and R1 are 2k banks, the low bit picks which half
+            0 | 1 => usize::from(self.bank_regs[0] & 0x3e) | (slot & 0x01),
+            2 | 3 => usize::from(self.bank_regs[1] & 0x3e) | (slot & 0x01),
+            _ => usize::from(self.bank_regs[slot - 2] & 0x3f),
+        };
+        match self.board {
+            //The registers still drive CHR A10-A15, PPU A12 just gets wired in on top as CHR A16
+            Namco108Board::Namcot3433 | Namco108Board::Namcot3453 => {
+                (bank & 0x3f) | if slot >= 4 { 0x40 } else { 0x00 }
+            }
+            //Bit 5 goes to the nametables instead
+            Namco108Board::Namcot3425 => bank & 0x1f,
+            _ => bank,
+        }
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        self.chr_bank(addr) * NAMCO108_CHR_BANK_SIZE + (usize::from(addr) & (NAMCO108_CHR_BANK_SIZE - 1))
+    }
+    //95 only, which console nametable a PPU nametable address ends up in. $2000-$27FF follows R0, $2800-$2FFF follows R1
+    fn nametable_page(&self, addr: u16) -> usize {
+        let reg = self.bank_regs[usize::from((addr >> 11) & 0x01)];
+        usize::from((reg >> 5) & 0x01)
+    }
+}
+
+impl Mapper for Namco108 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+        } else if !is_nondestructive {
+            //154 latches the mirroring bit on any write, the bank registers only listen at $8000-$9FFF
+            if self.board == Namco108Board::Namcot3453 {
+                self.is_upper_screen = (data & 0x40) == 0x40;
+            }
+            if addr < 0xa000 {
+                if (addr & 0x01) == 0x00 {
+                    self.bank_select = data & 0x07;
+                } else {
+                    self.bank_regs[usize::from(self.bank_select)] = data;
+                }
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.board {
+            Namco108Board::Namcot3453 if self.is_upper_screen => MirrorTable::SingleScreenUpper,
+            Namco108Board::Namcot3453 => MirrorTable::SingleScreenLower,
+            _ => self.data.header_mirror_table(),
+        }
+    }
+    fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        if self.board == Namco108Board::Namcot3425 {
+            let offset = usize::from(addr) & (NAME_TABLE_SIZE - 1);
+            nametables[self.nametable_page(addr)][offset]
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset]
+        }
+    }
+    fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        if self.board == Namco108Board::Namcot3425 {
+            let offset = usize::from(addr) & (NAME_TABLE_SIZE - 1);
+            nametables[self.nametable_page(addr)][offset] = data;
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset] = data;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //128k of CHR where every byte of a 1k bank holds that bank's number, so a read says which bank got mapped
+    fn new_mapper(board: Namco108Board) -> Namco108 {
+        let c_rom = (0..128).flat_map(|bank| vec![bank as u8; NAMCO108_CHR_BANK_SIZE]).collect();
+        Namco108::new(RomData { c_rom, ..RomData::default() }, board)
+    }
+    fn write_reg(mapper: &mut Namco108, reg: u8, data: u8) {
+        mapper.write_u8(0x8000, reg, false);
+        mapper.write_u8(0x8001, data, false);
+    }
+    fn chr_banks(mapper: &mut Namco108) -> [u8; 8] {
+        let mut banks = [0; 8];
+        for (slot, bank) in banks.iter_mut().enumerate() {
+            *bank = mapper.read_video_u8((slot as u16) << 10);
+        }
+        banks
+    }
+
+    #[test]
+    fn namco108_chr_layout() {
+        let mut mapper = new_mapper(Namco108Board::Namco108);
+        //R0 and R1 ignore the low bit
+        write_reg(&mut mapper, 0, 0x0b);
+        write_reg(&mut mapper, 1, 0x05);
+        write_reg(&mut mapper, 2, 0x20);
+        write_reg(&mut mapper, 3, 0x21);
+        write_reg(&mut mapper, 4, 0x3e);
+        write_reg(&mut mapper, 5, 0x3f);
+        assert_eq!(chr_banks(&mut mapper), [0x0a, 0x0b, 0x04, 0x05, 0x20, 0x21, 0x3e, 0x3f]);
+    }
+
+    #[test]
+    fn namcot3433_chr_a16() {
+        let mut mapper = new_mapper(Namco108Board::Namcot3433);
+        write_reg(&mut mapper, 0, 0x22);
+        write_reg(&mut mapper, 1, 0x3e);
+        write_reg(&mut mapper, 2, 0x00);
+        write_reg(&mut mapper, 3, 0x05);
+        write_reg(&mut mapper, 4, 0x20);
+        write_reg(&mut mapper, 5, 0x3f);
+        //The 2k banks stay in the first 64k, the 1k banks land in the second
+        assert_eq!(chr_banks(&mut mapper), [0x22, 0x23, 0x3e, 0x3f, 0x40, 0x45, 0x60, 0x7f]);
+    }
+
+    #[test]
+    fn namcot3453_one_screen() {
+        let mut mapper = new_mapper(Namco108Board::Namcot3453);
+        let mut nametables: NameTables = [[0; NAME_TABLE_SIZE]; NUM_OF_NAME_TABLE];
+        nametables[0][0] = 0xaa;
+        nametables[1][0] = 0xbb;
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2400), 0xaa);
+        //Bit 6 counts on any write, not just the bank registers
+        mapper.write_u8(0xc000, 0x40, false);
+        assert!(matches!(mapper.mirror_table(), MirrorTable::SingleScreenUpper));
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2000), 0xbb);
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2c00), 0xbb);
+        mapper.write_u8(0x8000, 0x00, false);
+        assert!(matches!(mapper.mirror_table(), MirrorTable::SingleScreenLower));
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2800), 0xaa);
+        //Still gets the A16 split like 88
+        write_reg(&mut mapper, 0, 0x30);
+        write_reg(&mut mapper, 5, 0x31);
+        let banks = chr_banks(&mut mapper);
+        assert_eq!((banks[0], banks[7]), (0x30, 0x71));
+    }
+
+    #[test]
+    fn namcot3446_2k_banks() {
+        let mut mapper = new_mapper(Namco108Board::Namcot3446);
+        write_reg(&mut mapper, 0, 0x10);
+        write_reg(&mut mapper, 1, 0x11);
+        write_reg(&mut mapper, 2, 0x01);
+        write_reg(&mut mapper, 3, 0x02);
+        write_reg(&mut mapper, 4, 0x20);
+        write_reg(&mut mapper, 5, 0x3f);
+        assert_eq!(chr_banks(&mut mapper), [0x02, 0x03, 0x04, 0x05, 0x40, 0x41, 0x7e, 0x7f]);
+    }
+
+    #[test]
+    fn namcot3425_nametable_select() {
+        let mut mapper = new_mapper(Namco108Board::Namcot3425);
+        let mut nametables: NameTables = [[0; NAME_TABLE_SIZE]; NUM_OF_NAME_TABLE];
+        nametables[0][0x10] = 0xaa;
+        nametables[1][0x10] = 0xbb;
+        write_reg(&mut mapper, 0, 0x22);
+        write_reg(&mut mapper, 1, 0x04);
+        //$2000-$27FF follows R0, $2800-$2FFF follows R1, and bit 5 doesn't reach CHR
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2010), 0xbb);
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2410), 0xbb);
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2810), 0xaa);
+        assert_eq!(mapper.read_nametable_u8(&nametables, 0x2c10), 0xaa);
+        assert_eq!(chr_banks(&mut mapper)[0], 0x02);
+        write_reg(&mut mapper, 1, 0x24);
+        mapper.write_nametable_u8(&mut nametables, 0x2c10, 0xcc);
+        assert_eq!(nametables[1][0x10], 0xcc);
+    }
+}
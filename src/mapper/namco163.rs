@@ -0,0 +1,326 @@
+/* Namco 163, 175 and 340, mappers 19 and 210 */
+//http://wiki.nesdev.com/w/index.php/Namco_163
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_210
+//Three switchable 8k PRG banks and eight 1k CHR banks on all three chips.
+//The 163 (mapper 19) also lets each nametable come from CHR-ROM instead of the console's RAM, has a 15-bit IRQ counter,
+//and a wavetable sound generator with 128 bytes of RAM that doubles as battery backed save memory.
+//The 175 and 340 (mapper 210, submapper 1 and 2) are the cheap versions. 175 has a RAM enable and soldered mirroring,
+//340 has mirroring control and no RAM.
+//http://wiki.nesdev.com/w/index.php/Namco_163_audio
+
+use super::Mapper;
+use crate::rom::*;
+use crate::video::*;
+
+pub const NAMCO163_PRG_BANK_SIZE: usize = 0x2000;
+pub const NAMCO163_CHR_BANK_SIZE: usize = 0x0400;
+pub const NAMCO163_INTERNAL_RAM_SIZE: usize = 0x80;
+//The sound generator does one channel every 15 CPU cycles
+pub const NAMCO163_AUDIO_CHANNEL_CYCLE: u8 = 15;
+//Channel registers live in the top of the internal RAM, 8 bytes each, the last channel at $78
+pub const NAMCO163_AUDIO_REG_BASE: usize = 0x40;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Namco163Board {
+    Namco163,
+    Namco175,
+    Namco340,
+}
+
+#[derive(Debug)]
+pub struct Namco163 {
+    data: RomData,
+    board: Namco163Board,
+    //$E000, $E800, $F000. The top bits carry other things depending on the chip
+    prg_banks: [u8; 3],
+    //$8000-$B800
+    chr_banks: [u8; 8],
+    //$C000-$D800, values $E0 and up pick the console's nametable RAM, anything else is a CHR-ROM bank
+    nametable_banks: [u8; 4],
+    //175 only, $C000 bit 0
+    is_sram_enable: bool,
+    //$F800 on the 163, RAM write protect plus the sound RAM address
+    sram_protect: u8,
+    //15-bit counter at $5000/$5800, counts up every CPU cycle while bit 15 is set and stops at $7FFF
+    irq_counter: u16,
+    is_irq: bool,
+    internal_ram: [u8; NAMCO163_INTERNAL_RAM_SIZE],
+    //$F800 bits 0-7 select the internal RAM address, bit 7 is auto increment
+    sound_addr: u8,
+    //Which channel the sound generator is on, and how long until it moves on
+    audio_channel: usize,
+    audio_cycle: u8,
+    //Only one channel is ever playing at any instant, this is whichever went last
+    audio_level: f32,
+}
+
+impl Namco163 {
+    pub fn new(data: RomData) -> Self {
+        let board = match (data.mapper_id, data.submapper_id) {
+            (19, _) => Namco163Board::Namco163,
+            (_, 2) => Namco163Board::Namco340,
+            //No submapper, most mapper 210 games are 175s
+            (_, _) => Namco163Board::Namco175,
+        };
+        Self {
+            data,
+            board,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [0xe0; 4],
+            is_sram_enable: false,
+            sram_protect: 0,
+            irq_counter: 0,
+            is_irq: false,
+            internal_ram: [0; NAMCO163_INTERNAL_RAM_SIZE],
+            sound_addr: 0,
+            audio_channel: 7,
+            audio_cycle: 0,
+            audio_level: 0.0,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / NAMCO163_PRG_BANK_SIZE;
+        let slot = usize::from((addr >> 13) & 0x03);
+        let bank = if slot < 3 {
+            usize::from(self.prg_banks[slot] & 0x3f)
+        } else {
+            num_of_banks.saturating_sub(1)
+        };
+        let offset = usize::from(addr) & (NAMCO163_PRG_BANK_SIZE - 1);
+        (bank * NAMCO163_PRG_BANK_SIZE + offset) % self.data.p_rom.len()
+    }
+    //The real 163 can also put nametable RAM in the pattern tables with CHR values $E0 and up,
+    //that needs the console's RAM on this side of the bus so we just treat them as ROM banks
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = usize::from(self.chr_banks[usize::from(addr >> 10) & 0x07]);
+        bank * NAMCO163_CHR_BANK_SIZE + (usize::from(addr) & (NAMCO163_CHR_BANK_SIZE - 1))
+    }
+    //163 RAM is written only with $F800 bits 4-7 set to 0100, and then bits 0-3 protect one 2k quarter each
+    fn is_sram_writable(&self, addr: u16) -> bool {
+        match self.board {
+            Namco163Board::Namco163 => {
+                let quarter = (addr - BATTERY_PACKED_RAM_BASE_ADDR) >> 11;
+                (self.sram_protect & 0xf0) == 0x40 && (self.sram_protect >> quarter) & 0x01 == 0x00
+            }
+            Namco163Board::Namco175 => self.is_sram_enable,
+            Namco163Board::Namco340 => false,
+        }
+    }
+    fn sram_index(&self, addr: u16) -> usize {
+        match self.board {
+            //175 only has 2k, mirrored through the whole window
+            Namco163Board::Namco175 => usize::from(addr) & 0x07ff,
+            _ => usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR),
+        }
+    }
+    fn read_sound_ram(&mut self, is_nondestructive: bool) -> u8 {
+        let data = self.internal_ram[usize::from(self.sound_addr & 0x7f)];
+        if !is_nondestructive {
+            self.step_sound_addr();
+        }
+        data
+    }
+    fn write_sound_ram(&mut self, data: u8) {
+        self.internal_ram[usize::from(self.sound_addr & 0x7f)] = data;
+        self.step_sound_addr();
+    }
+    fn step_sound_addr(&mut self) {
+        if (self.sound_addr & 0x80) == 0x80 {
+            self.sound_addr = 0x80 | (self.sound_addr.wrapping_add(1) & 0x7f);
+        }
+    }
+    fn num_of_audio_channels(&self) -> usize {
+        usize::from((self.internal_ram[0x7f] >> 4) & 0x07) + 1
+    }
+    //Runs one channel through its wave and returns the level, the chip cycles through the enabled channels one at a time
+    fn step_audio_channel(&mut self, channel: usize) -> f32 {
+        let base = NAMCO163_AUDIO_REG_BASE + channel * 8;
+        let regs = &mut self.internal_ram[base..base + 8];
+        let frequency = u32::from(regs[0]) | (u32::from(regs[2]) << 8) | (u32::from(regs[4] & 0x03) << 16);
+        let mut phase = u32::from(regs[1]) | (u32::from(regs[3]) << 8) | (u32::from(regs[5]) << 16);
+        //The wave length is 256 - (bits 2-7 * 4) samples, the phase has 16 bits of fraction under that
+        let length = 256 - u32::from(regs[4] & 0xfc);
+        phase = (phase + frequency) % (length << 16);
+        regs[1] = phase as u8;
+        regs[3] = (phase >> 8) as u8;
+        regs[5] = (phase >> 16) as u8;
+
+        let sample_addr = ((phase >> 16) + u32::from(regs[6])) & 0xff;
+        let volume = f32::from(regs[7] & 0x0f);
+        //Two 4-bit samples per byte, low nibble first
+        let byte = self.internal_ram[(sample_addr >> 1) as usize];
+        let sample = if (sample_addr & 0x01) == 0x01 { byte >> 4 } else { byte & 0x0f };
+        (f32::from(sample) - 8.0) * volume
+    }
+}
+
+impl Mapper for Namco163 {
+    fn read_u8(&mut self, addr: u16, is_nondestructive: bool) -> u8 {
+        if addr < BATTERY_PACKED_RAM_BASE_ADDR {
+            match addr & 0xf800 {
+                0x4800 => self.read_sound_ram(is_nondestructive),
+                0x5000 => self.irq_counter as u8,
+                0x5800 => (self.irq_counter >> 8) as u8,
+                _ => open_bus(addr),
+            }
+        } else if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            match self.board {
+                Namco163Board::Namco340 => open_bus(addr),
+                Namco163Board::Namco175 if !self.is_sram_enable => open_bus(addr),
+                _ => self.data.srambytes[self.sram_index(addr)],
+            }
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        if addr < BATTERY_PACKED_RAM_BASE_ADDR {
+            match addr & 0xf800 {
+                0x4800 => self.write_sound_ram(data),
+                //Writing either half of the counter acknowledges the IRQ
+                0x5000 => {
+                    self.irq_counter = (self.irq_counter & 0xff00) | u16::from(data);
+                    self.is_irq = false;
+                }
+                0x5800 => {
+                    self.irq_counter = (self.irq_counter & 0x00ff) | (u16::from(data) << 8);
+                    self.is_irq = false;
+                }
+                _ => {}
+            }
+        } else if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_sram_writable(addr) {
+                let index = self.sram_index(addr);
+                self.data.srambytes[index] = data;
+            }
+        } else {
+            match (addr & 0xf800, self.board) {
+                (0x8000..=0xb800, _) => self.chr_banks[usize::from((addr - 0x8000) >> 11)] = data,
+                (0xc000..=0xd800, Namco163Board::Namco163) => {
+                    self.nametable_banks[usize::from((addr - 0xc000) >> 11)] = data
+                }
+                (0xc000, Namco163Board::Namco175) => self.is_sram_enable = (data & 0x01) == 0x01,
+                (0xe000, _) => self.prg_banks[0] = data,
+                (0xe800, _) => self.prg_banks[1] = data,
+                (0xf000, _) => self.prg_banks[2] = data,
+                (0xf800, Namco163Board::Namco163) => {
+                    //The same register sets the sound RAM address and the write protect
+                    self.sram_protect = data;
+                    self.sound_addr = data;
+                }
+                _ => {}
+            }
+        }
+    }
+    //Sound RAM and the IRQ counter, only the 163 has them
+    fn is_expansion_area(&self) -> bool {
+        self.board == Namco163Board::Namco163
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.board {
+            //340 uses the top two bits of the first PRG bank
+            Namco163Board::Namco340 => match self.prg_banks[0] >> 6 {
+                0 => MirrorTable::SingleScreenLower,
+                1 => MirrorTable::Vertical,
+                2 => MirrorTable::Horizontal,
+                _ => MirrorTable::SingleScreenUpper,
+            },
+            _ => self.data.header_mirror_table(),
+        }
+    }
+    fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        let offset = usize::from(addr) & (NAME_TABLE_SIZE - 1);
+        if self.board != Namco163Board::Namco163 {
+            let (index, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            return nametables[index][offset];
+        }
+        let bank = self.nametable_banks[usize::from((addr >> 10) & 0x03)];
+        if bank >= 0xe0 {
+            nametables[usize::from(bank & 0x01)][offset]
+        } else {
+            self.data.read_chr(usize::from(bank) * NAMCO163_CHR_BANK_SIZE + offset)
+        }
+    }
+    fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        let offset = usize::from(addr) & (NAME_TABLE_SIZE - 1);
+        if self.board != Namco163Board::Namco163 {
+            let (index, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[index][offset] = data;
+            return;
+        }
+        let bank = self.nametable_banks[usize::from((addr >> 10) & 0x03)];
+        if bank >= 0xe0 {
+            nametables[usize::from(bank & 0x01)][offset] = data;
+        } else {
+            self.data.write_chr(usize::from(bank) * NAMCO163_CHR_BANK_SIZE + offset, data);
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.is_irq
+    }
+    fn step_cpu_cycle(&mut self) {
+        if self.board != Namco163Board::Namco163 {
+            return;
+        }
+        if (self.irq_counter & 0x8000) == 0x8000 && (self.irq_counter & 0x7fff) != 0x7fff {
+            self.irq_counter += 1;
+            if (self.irq_counter & 0x7fff) == 0x7fff {
+                self.is_irq = true;
+            }
+        }
+
+        self.audio_cycle += 1;
+        if self.audio_cycle < NAMCO163_AUDIO_CHANNEL_CYCLE {
+            return;
+        }
+        self.audio_cycle = 0;
+        //Channels go from 7 down, wrapping once all the enabled ones have had a turn
+        let first_channel = 8 - self.num_of_audio_channels();
+        self.audio_channel = if self.audio_channel <= first_channel {
+            7
+        } else {
+            self.audio_channel - 1
+        };
+        self.audio_level = self.step_audio_channel(self.audio_channel);
+    }
+    fn audio_output(&self) -> f32 {
+        //$E000 bit 6 turns the sound off
+        if self.board != Namco163Board::Namco163 || (self.prg_banks[0] & 0x40) == 0x40 {
+            0.0
+        } else {
+            //Switching between channels this fast is what mixes them, AudioOutput's averaging does the rest
+            self.audio_level * 0.0025
+        }
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if !self.data.sram {
+            return None;
+        }
+        //The sound RAM is battery backed too, some games keep their saves there
+        let mut data = self.data.srambytes.clone();
+        if self.board == Namco163Board::Namco163 {
+            data.extend_from_slice(&self.internal_ram);
+        }
+        Some(data)
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        let sram_len = self.data.srambytes.len().min(data.len());
+        self.data.srambytes[..sram_len].copy_from_slice(&data[..sram_len]);
+        if self.board == Namco163Board::Namco163 && data.len() > sram_len {
+            let rest = &data[sram_len..];
+            let ram_len = rest.len().min(NAMCO163_INTERNAL_RAM_SIZE);
+            self.internal_ram[..ram_len].copy_from_slice(&rest[..ram_len]);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+/* NROM, mapper 0 */
+//http://wiki.nesdev.com/w/index.php/NROM
+//No bank switching at all, 16k or 32k of PRG and 8k of CHR. Original Super Mario and Donkey Kong live here.
+
+use super::Mapper;
+use crate::rom::*;
+
+#[derive(Debug)]
+pub struct Nrom {
+    data: RomData,
+}
+
+impl Nrom {
+    pub fn new(data: RomData) -> Self {
+        Self { data }
+    }
+}
+
+impl Mapper for Nrom {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            //16k games just show up twice
+            let index = usize::from(addr - PRG_ROM_SYSTEM_BASE_ADDR) % self.data.p_rom.len();
+            self.data.p_rom[index]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, _is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+        }
+        //It's ROM, writes up there don't go anywhere
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(usize::from(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        self.data.write_chr(usize::from(addr), data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        self.data.header_mirror_table()
+    }
+}
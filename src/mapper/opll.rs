@@ -0,0 +1,366 @@
+/* The VRC7's FM synthesizer, a cut down Yamaha YM2413 (OPLL) */
+//http://wiki.nesdev.com/w/index.php/VRC7_audio
+//Six channels, each one a modulator operator feeding a carrier operator. An instrument (patch) sets up both operators,
+//there are 15 of them baked into the chip and one the game can write itself.
+//This works in floating point rather than copying the chip's log/exp tables bit for bit, it won't match a real VRC7
+//sample for sample but the instruments sound right.
+
+use std::f32::consts::PI;
+
+pub const OPLL_NUM_OF_CHANNELS: usize = 6;
+//The chip makes one sample every 72 clocks, with its 3.58MHz clock that's every 36 CPU cycles
+pub const OPLL_CPU_CYCLES_PER_SAMPLE: u8 = 36;
+pub const OPLL_SAMPLE_RATE: f32 = 49716.0;
+//Envelope levels count in 0.375dB steps, 128 of them is as quiet as it gets
+pub const OPLL_ENVELOPE_MAX: f32 = 128.0;
+
+//The instruments in the VRC7, dumped from a real chip. Patch 0 is the custom one so it's left empty
+const BUILTIN_PATCHES: [[u8; 8]; 16] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x03, 0x21, 0x05, 0x06, 0xe8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0d, 0xd8, 0xf6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xfa, 0xb2, 0x20, 0x12],
+    [0x31, 0x61, 0x0c, 0x07, 0xa8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1e, 0x06, 0xe1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xa3, 0xe2, 0xf4, 0xf4],
+    [0x21, 0x61, 0x1d, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xa2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xb5, 0x01, 0x0f, 0x0f, 0xa8, 0xa5, 0x51, 0x02],
+    [0x17, 0xc1, 0x24, 0x07, 0xf8, 0xf8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xd3, 0x05, 0xc9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0c, 0x00, 0x94, 0xc0, 0x33, 0xf6],
+    [0x21, 0x72, 0x0d, 0x00, 0xc1, 0xd5, 0x56, 0x06],
+];
+//Frequency multiplier, doubled so it fits in an integer (0 means x0.5)
+const MULTIPLIER_X2: [u8; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+//Key scale level in dB at octave 7, by the top 4 bits of the frequency
+const KEY_SCALE_TABLE: [f32; 16] = [
+    0.0, 18.0, 24.0, 27.75, 30.0, 32.25, 33.75, 35.25, 36.0, 37.5, 38.25, 39.0, 39.75, 40.5, 41.25,
+    42.0,
+];
+//Tremolo is 4.8dB deep at 3.7Hz, vibrato is a few cents at 6.4Hz
+const TREMOLO_DEPTH: f32 = 4.8;
+const TREMOLO_FREQ: f32 = 3.7;
+const VIBRATO_DEPTH: f32 = 0.004;
+const VIBRATO_FREQ: f32 = 6.4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+//One operator's half of a patch
+#[derive(Copy, Clone, Debug, Default)]
+struct OperatorPatch {
+    is_tremolo: bool,
+    is_vibrato: bool,
+    //The envelope holds at the sustain level instead of carrying on down
+    is_sustained: bool,
+    is_key_scale_rate: bool,
+    multiplier: u8,
+    key_scale_level: u8,
+    //Only the negative half of the sine is cut
+    is_rectified: bool,
+    attack_rate: u8,
+    decay_rate: u8,
+    sustain_level: u8,
+    release_rate: u8,
+}
+
+impl OperatorPatch {
+    //Picks one operator out of the 8 register layout
+    fn from_registers(regs: &[u8; 8], is_carrier: bool) -> Self {
+        let index = if is_carrier { 1 } else { 0 };
+        let rectify_bit = if is_carrier { 0x10 } else { 0x08 };
+        let flags = regs[index];
+        let ksl_reg = regs[2 + index];
+        let rates = regs[4 + index];
+        let levels = regs[6 + index];
+        Self {
+            is_tremolo: (flags & 0x80) == 0x80,
+            is_vibrato: (flags & 0x40) == 0x40,
+            is_sustained: (flags & 0x20) == 0x20,
+            is_key_scale_rate: (flags & 0x10) == 0x10,
+            multiplier: flags & 0x0f,
+            key_scale_level: ksl_reg >> 6,
+            is_rectified: (regs[3] & rectify_bit) == rectify_bit,
+            attack_rate: rates >> 4,
+            decay_rate: rates & 0x0f,
+            sustain_level: levels >> 4,
+            release_rate: levels & 0x0f,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Operator {
+    //Where we are in the sine wave, in whole periods
+    phase: f32,
+    envelope: f32,
+    envelope_state: EnvelopeState,
+    //Last two outputs, the modulator feeds these back into itself
+    outputs: [f32; 2],
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Self {
+            phase: 0.0,
+            envelope: OPLL_ENVELOPE_MAX,
+            envelope_state: EnvelopeState::Release,
+            outputs: [0.0; 2],
+        }
+    }
+}
+
+impl Operator {
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.envelope_state = EnvelopeState::Attack;
+    }
+    fn key_off(&mut self) {
+        self.envelope_state = EnvelopeState::Release;
+    }
+    //How many envelope steps one sample moves for a 0-15 rate. Each rate is twice as fast as the one before it,
+    //with the key scale bits filling in between
+    fn envelope_step(rate: u8, key_scale: u8) -> f32 {
+        if rate == 0 {
+            return 0.0;
+        }
+        let effective = (rate * 4 + key_scale).min(63);
+        let fraction = f32::from(4 + (effective & 0x03));
+        fraction * (2.0f32).powi(i32::from(effective >> 2)) / 65536.0
+    }
+    fn step_envelope(&mut self, patch: &OperatorPatch, key_scale: u8, is_channel_sustain: bool) {
+        match self.envelope_state {
+            EnvelopeState::Attack => {
+                if patch.attack_rate == 15 {
+                    self.envelope = 0.0;
+                } else {
+                    //Attack is exponential, it rushes in from silence then slows down near full volume
+                    let step = Self::envelope_step(patch.attack_rate, key_scale);
+                    self.envelope -= (self.envelope / 8.0 + 1.0) * step;
+                }
+                if self.envelope <= 0.0 {
+                    self.envelope = 0.0;
+                    self.envelope_state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                self.envelope += Self::envelope_step(patch.decay_rate, key_scale);
+                //Each sustain level step is 3dB
+                let sustain = f32::from(patch.sustain_level) * 8.0;
+                if self.envelope >= sustain {
+                    self.envelope = sustain;
+                    self.envelope_state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain => {
+                //Percussive sounds keep fading while the key is down
+                if !patch.is_sustained {
+                    self.envelope += Self::envelope_step(patch.release_rate, key_scale);
+                }
+            }
+            EnvelopeState::Release => {
+                let rate = if is_channel_sustain {
+                    5
+                } else if patch.is_sustained {
+                    patch.release_rate
+                } else {
+                    7
+                };
+                self.envelope += Self::envelope_step(rate, key_scale);
+            }
+        }
+        self.envelope = self.envelope.min(OPLL_ENVELOPE_MAX);
+    }
+    //Moves the envelope and the phase along one sample
+    fn step(&mut self, patch: &OperatorPatch, base_step: f32, key_scale_full: u8, vibrato: f32, is_channel_sustain: bool) {
+        //Key scale rate only uses the top two bits unless KSR is set
+        let key_scale = if patch.is_key_scale_rate {
+            key_scale_full
+        } else {
+            key_scale_full >> 2
+        };
+        self.step_envelope(patch, key_scale, is_channel_sustain);
+        let mut step = base_step * f32::from(MULTIPLIER_X2[usize::from(patch.multiplier)]) / 2.0;
+        if patch.is_vibrato {
+            step *= vibrato;
+        }
+        self.phase = (self.phase + step).fract();
+    }
+    //Returns -1.0 to 1.0 after the envelope and the given attenuation (in dB)
+    fn output(&mut self, patch: &OperatorPatch, phase_offset: f32, attenuation: f32) -> f32 {
+        let total = self.envelope * 0.375 + attenuation;
+        let wave = (2.0 * PI * (self.phase + phase_offset)).sin();
+        let wave = if patch.is_rectified && wave < 0.0 { 0.0 } else { wave };
+        let level = if self.envelope >= OPLL_ENVELOPE_MAX {
+            0.0
+        } else {
+            wave * (10.0f32).powf(-total / 20.0)
+        };
+        self.outputs = [self.outputs[1], level];
+        level
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Channel {
+    //$10-$15 and bit 0 of $20-$25, 9 bits
+    frequency: u16,
+    //$20-$25 bits 1-3
+    octave: u8,
+    is_key_on: bool,
+    is_sustain: bool,
+    //$30-$35
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+}
+
+#[derive(Clone, Debug)]
+pub struct Opll {
+    //The register $9030 writes go to
+    selected_reg: u8,
+    custom_patch: [u8; 8],
+    channels: Vec<Channel>,
+    //Shared low frequency oscillators for tremolo and vibrato, in periods
+    tremolo_phase: f32,
+    vibrato_phase: f32,
+    cycle_count: u8,
+    output: f32,
+}
+
+impl Default for Opll {
+    fn default() -> Self {
+        Self {
+            selected_reg: 0,
+            custom_patch: [0; 8],
+            channels: vec![Channel::default(); OPLL_NUM_OF_CHANNELS],
+            tremolo_phase: 0.0,
+            vibrato_phase: 0.0,
+            cycle_count: 0,
+            output: 0.0,
+        }
+    }
+}
+
+impl Opll {
+    pub fn select_register(&mut self, data: u8) {
+        self.selected_reg = data;
+    }
+    pub fn write_register(&mut self, data: u8) {
+        let reg = self.selected_reg;
+        let index = usize::from(reg & 0x0f);
+        match reg & 0xf0 {
+            0x00 if index < 8 => self.custom_patch[index] = data,
+            0x10 if index < OPLL_NUM_OF_CHANNELS => {
+                let channel = &mut self.channels[index];
+                channel.frequency = (channel.frequency & 0x100) | u16::from(data);
+            }
+            0x20 if index < OPLL_NUM_OF_CHANNELS => {
+                let channel = &mut self.channels[index];
+                channel.frequency = (channel.frequency & 0xff) | (u16::from(data & 0x01) << 8);
+                channel.octave = (data >> 1) & 0x07;
+                channel.is_sustain = (data & 0x20) == 0x20;
+                let is_key_on = (data & 0x10) == 0x10;
+                if is_key_on && !channel.is_key_on {
+                    channel.modulator.key_on();
+                    channel.carrier.key_on();
+                } else if !is_key_on && channel.is_key_on {
+                    channel.modulator.key_off();
+                    channel.carrier.key_off();
+                }
+                channel.is_key_on = is_key_on;
+            }
+            0x30 if index < OPLL_NUM_OF_CHANNELS => {
+                let channel = &mut self.channels[index];
+                channel.instrument = data >> 4;
+                channel.volume = data & 0x0f;
+            }
+            _ => {}
+        }
+    }
+    pub fn output(&self) -> f32 {
+        self.output
+    }
+    //Called every CPU cycle, a new sample comes out every 36
+    pub fn step_cpu_cycle(&mut self) {
+        self.cycle_count += 1;
+        if self.cycle_count < OPLL_CPU_CYCLES_PER_SAMPLE {
+            return;
+        }
+        self.cycle_count = 0;
+        self.output = self.step_sample();
+    }
+    fn patch_registers(&self, instrument: u8) -> [u8; 8] {
+        if instrument == 0 {
+            self.custom_patch
+        } else {
+            BUILTIN_PATCHES[usize::from(instrument)]
+        }
+    }
+    fn step_sample(&mut self) -> f32 {
+        self.tremolo_phase = (self.tremolo_phase + TREMOLO_FREQ / OPLL_SAMPLE_RATE).fract();
+        self.vibrato_phase = (self.vibrato_phase + VIBRATO_FREQ / OPLL_SAMPLE_RATE).fract();
+        let tremolo = (1.0 - (2.0 * PI * self.tremolo_phase).cos()) * 0.5 * TREMOLO_DEPTH;
+        let vibrato = 1.0 + (2.0 * PI * self.vibrato_phase).sin() * VIBRATO_DEPTH;
+
+        let mut mix = 0.0;
+        for index in 0..OPLL_NUM_OF_CHANNELS {
+            let regs = self.patch_registers(self.channels[index].instrument);
+            let modulator_patch = OperatorPatch::from_registers(&regs, false);
+            let carrier_patch = OperatorPatch::from_registers(&regs, true);
+            let modulator_level = f32::from(regs[2] & 0x3f) * 0.75;
+            let feedback = regs[3] & 0x07;
+
+            let channel = &mut self.channels[index];
+            let block = channel.octave;
+            //Key scale rate goes by the octave and the top frequency bit
+            let key_scale_full = (block << 1) | ((channel.frequency >> 8) as u8);
+            //Key scale level gets quieter the higher the note
+            let ksl_base = (KEY_SCALE_TABLE[usize::from(channel.frequency >> 5)] - 6.0 * f32::from(7 - block)).max(0.0);
+            let ksl_scale = [0.0, 0.25, 0.5, 1.0];
+            //The frequency in periods per sample before the multiplier, f = fnum * 2^block * rate / 2^19
+            let base_step = f32::from(channel.frequency) * (2.0f32).powi(i32::from(block)) / 524_288.0;
+
+            channel.modulator.step(&modulator_patch, base_step, key_scale_full, vibrato, channel.is_sustain);
+            channel.carrier.step(&carrier_patch, base_step, key_scale_full, vibrato, channel.is_sustain);
+            let attenuation = |patch: &OperatorPatch| {
+                let ksl = ksl_base * ksl_scale[usize::from(patch.key_scale_level)];
+                if patch.is_tremolo {
+                    ksl + tremolo
+                } else {
+                    ksl
+                }
+            };
+
+            //The modulator feeds back into itself
+            let feedback_offset = if feedback == 0 {
+                0.0
+            } else {
+                let outputs = channel.modulator.outputs;
+                (outputs[0] + outputs[1]) * (2.0f32).powi(i32::from(feedback) - 7)
+            };
+            let modulator_out = channel.modulator.output(
+                &modulator_patch,
+                feedback_offset,
+                attenuation(&modulator_patch) + modulator_level,
+            );
+            //Then bends the carrier's phase by up to two periods either way
+            let carrier_out = channel.carrier.output(
+                &carrier_patch,
+                modulator_out * 2.0,
+                attenuation(&carrier_patch) + f32::from(channel.volume) * 3.0,
+            );
+            mix += carrier_out;
+        }
+        mix / (OPLL_NUM_OF_CHANNELS as f32)
+    }
+}
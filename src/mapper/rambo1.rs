@@ -0,0 +1,323 @@
+/* Tengen RAMBO-1, mapper 64 */
+//http://wiki.nesdev.com/w/index.php/RAMBO-1
+//Tengen's take on the MMC3. Three switchable PRG banks instead of two, two extra CHR registers for a full 1k CHR mode,
+//and an IRQ counter that can count either PPU A12 rises like the MMC3 or every 4 CPU cycles.
+//Klax, Shinobi and Skull & Crossbones use the cycle mode.
+//The IRQ line goes low a little after the counter hits zero rather than straight away, and games time their raster
+//effects around that, so the delay is kept here.
+
+use super::mmc3::MMC3_A12_FILTER;
+use super::Mapper;
+use crate::rom::*;
+
+pub const RAMBO1_PRG_BANK_SIZE: usize = 0x2000;
+pub const RAMBO1_CHR_BANK_SIZE: usize = 0x0400;
+//How many CPU cycles after the counter reaches zero the IRQ actually shows up, for each clock source
+pub const RAMBO1_A12_IRQ_DELAY: u8 = 2;
+pub const RAMBO1_CPU_IRQ_DELAY: u8 = 1;
+//In cycle mode the counter is clocked once every 4 CPU cycles
+pub const RAMBO1_CPU_CLOCK_DIVIDER: u8 = 4;
+
+#[derive(Debug)]
+pub struct Rambo1 {
+    data: RomData,
+    //$8000, bits 0-3 pick the register $8001 writes to, bit 5 is 1k CHR mode, bit 6 PRG mode, bit 7 CHR inversion
+    bank_select: u8,
+    //R0-R9 and RF, the numbers in between don't exist so they're just never read
+    bank_regs: [u8; 16],
+    //$A000, 0 is vertical and 1 is horizontal
+    mirroring: u8,
+    //$C000
+    irq_latch: u8,
+    irq_counter: u8,
+    is_irq_reload: bool,
+    //$C001 bit 0, counts CPU cycles instead of A12
+    is_cycle_mode: bool,
+    //$E000/$E001
+    is_irq_enable: bool,
+    is_irq: bool,
+    //Counts down to the IRQ going out, 0 when nothing is waiting
+    irq_delay: u8,
+    cpu_clock_divider: u8,
+    a12_low_count: u8,
+}
+
+impl Rambo1 {
+    pub fn new(data: RomData) -> Self {
+        Self {
+            data,
+            bank_select: 0,
+            bank_regs: [0; 16],
+            mirroring: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            is_irq_reload: false,
+            is_cycle_mode: false,
+            is_irq_enable: false,
+            is_irq: false,
+            irq_delay: 0,
+            cpu_clock_divider: 0,
+            a12_low_count: 0,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / RAMBO1_PRG_BANK_SIZE;
+        let is_prg_inverted = (self.bank_select & 0x40) == 0x40;
+        let reg = match ((addr >> 13) & 0x03, is_prg_inverted) {
+            (0, false) => Some(6),
+            (1, false) => Some(7),
+            (2, false) => Some(15),
+            //Inverted mode rotates the three banks round by one
+            (0, true) => Some(15),
+            (1, true) => Some(6),
+            (2, true) => Some(7),
+            _ => None,
+        };
+        let bank = match reg {
+            Some(reg) => usize::from(self.bank_regs[reg]),
+            None => num_of_banks.saturating_sub(1),
+        };
+        let offset = usize::from(addr) & (RAMBO1_PRG_BANK_SIZE - 1);
+        (bank * RAMBO1_PRG_BANK_SIZE + offset) % self.data.p_rom.len()
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let inverted_addr = if (self.bank_select & 0x80) == 0x80 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+        let slot = usize::from(inverted_addr >> 10) & 0x07;
+        let is_1k_mode = (self.bank_select & 0x20) == 0x20;
+        let bank = match (slot, is_1k_mode) {
+            //In 1k mode the second half of each 2k bank comes from R8 and R9
+            (0, true) => usize::from(self.bank_regs[0]),
+            (1, true) => usize::from(self.bank_regs[8]),
+            (2, true) => usize::from(self.bank_regs[1]),
+            (3, true) => usize::from(self.bank_regs[9]),
+            (0, false) | (1, false) => usize::from(self.bank_regs[0] & 0xfe) | (slot & 0x01),
+            (2, false) | (3, false) => usize::from(self.bank_regs[1] & 0xfe) | (slot & 0x01),
+            _ => usize::from(self.bank_regs[slot - 2]),
+        };
+        bank * RAMBO1_CHR_BANK_SIZE + (usize::from(addr) & (RAMBO1_CHR_BANK_SIZE - 1))
+    }
+    fn clock_irq_counter(&mut self, delay: u8) {
+        if self.is_irq_reload {
+            //A reload right after $C001 lands one further out than a normal reload does
+            self.irq_counter = if self.irq_latch <= 1 {
+                self.irq_latch.wrapping_add(1)
+            } else {
+                self.irq_latch.wrapping_add(2)
+            };
+            self.is_irq_reload = false;
+        } else if self.irq_counter == 0 {
+            self.irq_counter = self.irq_latch.wrapping_add(1);
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+        if self.irq_counter == 0 && self.is_irq_enable {
+            self.irq_delay = delay;
+        }
+    }
+    fn watch_a12(&mut self, addr: u16) {
+        if (addr & 0x1000) == 0x1000 {
+            if self.a12_low_count >= MMC3_A12_FILTER && !self.is_cycle_mode {
+                self.clock_irq_counter(RAMBO1_A12_IRQ_DELAY);
+            }
+            self.a12_low_count = 0;
+        } else {
+            self.a12_low_count = self.a12_low_count.saturating_add(1);
+        }
+    }
+}
+
+impl Mapper for Rambo1 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+        } else if !is_nondestructive {
+            let is_odd = (addr & 0x01) == 0x01;
+            match (addr & 0xe000, is_odd) {
+                (0x8000, false) => self.bank_select = data,
+                (0x8000, true) => self.bank_regs[usize::from(self.bank_select & 0x0f)] = data,
+                (0xa000, false) => self.mirroring = data & 0x01,
+                (0xa000, true) => {}
+                (0xc000, false) => self.irq_latch = data,
+                (0xc000, true) => {
+                    self.is_cycle_mode = (data & 0x01) == 0x01;
+                    self.is_irq_reload = true;
+                    self.cpu_clock_divider = 0;
+                }
+                (0xe000, false) => {
+                    self.is_irq_enable = false;
+                    self.is_irq = false;
+                    self.irq_delay = 0;
+                }
+                _ => self.is_irq_enable = true,
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.watch_a12(addr);
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        self.watch_a12(addr);
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        if self.mirroring == 0 {
+            MirrorTable::Vertical
+        } else {
+            MirrorTable::Horizontal
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.is_irq
+    }
+    fn step_cpu_cycle(&mut self) {
+        if self.irq_delay > 0 {
+            self.irq_delay -= 1;
+            if self.irq_delay == 0 {
+                self.is_irq = true;
+            }
+        }
+        if self.is_cycle_mode {
+            self.cpu_clock_divider = (self.cpu_clock_divider + 1) % RAMBO1_CPU_CLOCK_DIVIDER;
+            if self.cpu_clock_divider == 0 {
+                self.clock_irq_counter(RAMBO1_CPU_IRQ_DELAY);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //256k PRG and 256k CHR where every byte of a bank holds that bank's number
+    fn new_mapper() -> Rambo1 {
+        let p_rom = (0..32).flat_map(|bank| vec![bank as u8; RAMBO1_PRG_BANK_SIZE]).collect();
+        let c_rom = (0..256).flat_map(|bank| vec![bank as u8; RAMBO1_CHR_BANK_SIZE]).collect();
+        Rambo1::new(RomData { mapper_id: 64, p_rom, c_rom, ..RomData::default() })
+    }
+    fn write_reg(mapper: &mut Rambo1, bank_select: u8, data: u8) {
+        mapper.write_u8(0x8000, bank_select, false);
+        mapper.write_u8(0x8001, data, false);
+    }
+    fn prg_banks(mapper: &mut Rambo1) -> [u8; 4] {
+        [0x8000, 0xa000, 0xc000, 0xe000].map(|addr| mapper.read_u8(addr, false))
+    }
+    fn chr_banks(mapper: &mut Rambo1) -> [u8; 8] {
+        let mut banks = [0; 8];
+        for (slot, bank) in banks.iter_mut().enumerate() {
+            *bank = mapper.read_video_u8((slot as u16) << 10);
+        }
+        banks
+    }
+    fn render_line(mapper: &mut Rambo1) {
+        for _ in 0..32 {
+            mapper.read_video_u8(0x0000);
+        }
+        for _ in 0..8 {
+            mapper.read_video_u8(0x1000);
+        }
+    }
+    fn step_cpu_cycles(mapper: &mut Rambo1, count: usize) {
+        for _ in 0..count {
+            mapper.step_cpu_cycle();
+        }
+    }
+
+    #[test]
+    fn prg_banks_and_inversion() {
+        let mut mapper = new_mapper();
+        write_reg(&mut mapper, 6, 1);
+        write_reg(&mut mapper, 7, 2);
+        write_reg(&mut mapper, 15, 3);
+        assert_eq!(prg_banks(&mut mapper), [1, 2, 3, 31]);
+        //Inverted mode rotates them round by one
+        mapper.write_u8(0x8000, 0x40, false);
+        assert_eq!(prg_banks(&mut mapper), [3, 1, 2, 31]);
+    }
+
+    #[test]
+    fn chr_banks_1k_mode() {
+        let mut mapper = new_mapper();
+        for (reg, bank) in [(0, 0x11), (1, 0x21), (2, 0x30), (3, 0x31), (4, 0x32), (5, 0x33), (8, 0x40), (9, 0x41)] {
+            write_reg(&mut mapper, reg, bank);
+        }
+        assert_eq!(chr_banks(&mut mapper), [0x10, 0x11, 0x20, 0x21, 0x30, 0x31, 0x32, 0x33]);
+        //R8 and R9 take over the second half of the 2k banks
+        mapper.write_u8(0x8000, 0x20, false);
+        assert_eq!(chr_banks(&mut mapper), [0x11, 0x40, 0x21, 0x41, 0x30, 0x31, 0x32, 0x33]);
+        mapper.write_u8(0x8000, 0xa0, false);
+        assert_eq!(chr_banks(&mut mapper), [0x30, 0x31, 0x32, 0x33, 0x11, 0x40, 0x21, 0x41]);
+    }
+
+    #[test]
+    fn cycle_irq() {
+        let mut mapper = new_mapper();
+        mapper.write_u8(0xc000, 2, false);
+        mapper.write_u8(0xc001, 1, false);
+        mapper.write_u8(0xe001, 0, false);
+        //Clocked every 4 cycles, the first reload lands at latch + 1 so that's 4 clocks, then 1 cycle of delay
+        step_cpu_cycles(&mut mapper, 16);
+        assert!(!mapper.is_irq());
+        mapper.step_cpu_cycle();
+        assert!(mapper.is_irq());
+        mapper.write_u8(0xe000, 0, false);
+        mapper.write_u8(0xe001, 0, false);
+        assert!(!mapper.is_irq());
+        //After that it's latch + 1 clocks round
+        step_cpu_cycles(&mut mapper, 11);
+        assert!(!mapper.is_irq());
+        mapper.step_cpu_cycle();
+        assert!(mapper.is_irq());
+    }
+
+    #[test]
+    fn a12_irq() {
+        let mut mapper = new_mapper();
+        mapper.write_u8(0xc000, 1, false);
+        mapper.write_u8(0xc001, 0, false);
+        mapper.write_u8(0xe001, 0, false);
+        render_line(&mut mapper);
+        render_line(&mut mapper);
+        //The counter is at zero now, but the line only drops 2 CPU cycles later
+        assert!(!mapper.is_irq());
+        mapper.step_cpu_cycle();
+        assert!(!mapper.is_irq());
+        mapper.step_cpu_cycle();
+        assert!(mapper.is_irq());
+        //Cycles don't clock it in A12 mode
+        mapper.write_u8(0xe000, 0, false);
+        mapper.write_u8(0xe001, 0, false);
+        step_cpu_cycles(&mut mapper, 100);
+        assert!(!mapper.is_irq());
+        render_line(&mut mapper);
+        render_line(&mut mapper);
+        step_cpu_cycles(&mut mapper, 2);
+        assert!(mapper.is_irq());
+    }
+
+    #[test]
+    fn irq_disable_cancels_delay() {
+        let mut mapper = new_mapper();
+        mapper.write_u8(0xc000, 0, false);
+        mapper.write_u8(0xc001, 0, false);
+        mapper.write_u8(0xe001, 0, false);
+        render_line(&mut mapper);
+        mapper.write_u8(0xe000, 0, false);
+        step_cpu_cycles(&mut mapper, 4);
+        assert!(!mapper.is_irq());
+    }
+}
@@ -0,0 +1,221 @@
+/* Jaleco SS88006, mapper 18 */
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_018
+//Three switchable 8k PRG banks plus the last one fixed, eight 1k CHR banks, 8k of RAM, and an IRQ counter that counts
+//CPU cycles and can be cut down to 12, 8 or 4 bits. Every bank register is written 4 bits at a time, low nibble first.
+//Pizza Pop!, Ninja Jajamaru: Ginga Daisakusen, Magical John.
+//Registers are picked by addr & $F003:
+//  $8000-$9001: PRG banks, $9002: RAM enable, $A000-$D003: CHR banks, $E000-$E003: IRQ reload value
+//  $F000: IRQ reload, $F001: IRQ control, $F002: mirroring, $F003: speech chip
+
+use super::Mapper;
+use crate::rom::*;
+
+pub const SS88006_PRG_BANK_SIZE: usize = 0x2000;
+pub const SS88006_CHR_BANK_SIZE: usize = 0x0400;
+
+#[derive(Debug)]
+pub struct Ss88006 {
+    data: RomData,
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    //$9002, bit 0 enables the RAM and bit 1 allows writes
+    ram_control: u8,
+    mirroring: u8,
+    irq_reload: u16,
+    irq_counter: u16,
+    //Which bits of the counter actually count, from $F001
+    irq_mask: u16,
+    is_irq_enable: bool,
+    is_irq: bool,
+}
+
+impl Ss88006 {
+    pub fn new(data: RomData) -> Self {
+        Self {
+            data,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            ram_control: 0,
+            mirroring: 0,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_mask: 0xffff,
+            is_irq_enable: false,
+            is_irq: false,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / SS88006_PRG_BANK_SIZE;
+        let slot = usize::from((addr >> 13) & 0x03);
+        let bank = if slot < 3 {
+            usize::from(self.prg_banks[slot])
+        } else {
+            num_of_banks.saturating_sub(1)
+        };
+        (bank * SS88006_PRG_BANK_SIZE + (usize::from(addr) & (SS88006_PRG_BANK_SIZE - 1))) % self.data.p_rom.len()
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = usize::from(self.chr_banks[usize::from(addr >> 10) & 0x07]);
+        bank * SS88006_CHR_BANK_SIZE + (usize::from(addr) & (SS88006_CHR_BANK_SIZE - 1))
+    }
+    //Even addresses write the low nibble, odd ones the high
+    fn write_nibble(reg: &mut u8, addr: u16, data: u8) {
+        if (addr & 0x01) == 0x00 {
+            *reg = (*reg & 0xf0) | (data & 0x0f);
+        } else {
+            *reg = (*reg & 0x0f) | ((data & 0x0f) << 4);
+        }
+    }
+}
+
+impl Mapper for Ss88006 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if (self.ram_control & 0x01) == 0x01 {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index]
+            } else {
+                open_bus(addr)
+            }
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if (self.ram_control & 0x03) == 0x03 {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index] = data;
+            }
+            return;
+        }
+        match addr & 0xf003 {
+            0x8000 | 0x8001 => Self::write_nibble(&mut self.prg_banks[0], addr, data),
+            0x8002 | 0x8003 => Self::write_nibble(&mut self.prg_banks[1], addr, data),
+            0x9000 | 0x9001 => Self::write_nibble(&mut self.prg_banks[2], addr, data),
+            0x9002 => self.ram_control = data & 0x03,
+            //Two CHR registers per $1000 of address space, $A000-$A003 is R0 and R1
+            reg @ 0xa000..=0xd003 => {
+                let index = usize::from(((reg - 0xa000) >> 11) | ((reg >> 1) & 0x01));
+                Self::write_nibble(&mut self.chr_banks[index], addr, data);
+            }
+            reg @ 0xe000..=0xe003 => {
+                let shift = (reg & 0x03) * 4;
+                self.irq_reload = (self.irq_reload & !(0x0f << shift)) | (u16::from(data & 0x0f) << shift);
+            }
+            0xf000 => {
+                self.is_irq = false;
+                self.irq_counter = self.irq_reload;
+            }
+            0xf001 => {
+                self.is_irq = false;
+                self.is_irq_enable = (data & 0x01) == 0x01;
+                self.irq_mask = if (data & 0x08) == 0x08 {
+                    0x000f
+                } else if (data & 0x04) == 0x04 {
+                    0x00ff
+                } else if (data & 0x02) == 0x02 {
+                    0x0fff
+                } else {
+                    0xffff
+                };
+            }
+            0xf002 => self.mirroring = data & 0x03,
+            //$F003 is the uPD7756 ADPCM speech chip (Moero!! Pro Yakyuu). The samples are on a mask ROM inside the chip
+            //that isn't in any dump, so there's nothing to play and the start/reset writes go nowhere
+            _ => {}
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.mirroring {
+            0 => MirrorTable::Horizontal,
+            1 => MirrorTable::Vertical,
+            2 => MirrorTable::SingleScreenLower,
+            _ => MirrorTable::SingleScreenUpper,
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.is_irq
+    }
+    fn step_cpu_cycle(&mut self) {
+        if !self.is_irq_enable {
+            return;
+        }
+        //Only the low bits count, the rest of the counter is left alone
+        let counter = (self.irq_counter & self.irq_mask).wrapping_sub(1) & self.irq_mask;
+        self.irq_counter = (self.irq_counter & !self.irq_mask) | counter;
+        if counter == 0 {
+            self.is_irq = true;
+        }
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.data.battery_save_data()
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.data.load_battery_save_data(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_mapper() -> Ss88006 {
+        let p_rom = (0..16).flat_map(|bank| vec![bank as u8; SS88006_PRG_BANK_SIZE]).collect();
+        Ss88006::new(RomData { mapper_id: 18, p_rom, ..RomData::default() })
+    }
+    //Reload value $1234 a nibble at a time, then start the counter with the given $F001 size bits
+    fn start_irq(mapper: &mut Ss88006, irq_control: u8) {
+        for (addr, data) in [(0xe000, 0x4), (0xe001, 0x3), (0xe002, 0x2), (0xe003, 0x1)] {
+            mapper.write_u8(addr, data, false);
+        }
+        mapper.write_u8(0xf000, 0, false);
+        mapper.write_u8(0xf001, irq_control | 0x01, false);
+    }
+    fn step_cpu_cycles(mapper: &mut Ss88006, count: usize) {
+        for _ in 0..count {
+            mapper.step_cpu_cycle();
+        }
+    }
+
+    #[test]
+    fn irq_counter_sizes() {
+        for (irq_control, cycles, upper_bits) in [(0x00, 0x1234, 0x0000), (0x02, 0x234, 0x1000), (0x04, 0x34, 0x1200), (0x08, 0x4, 0x1230)] {
+            let mut mapper = new_mapper();
+            start_irq(&mut mapper, irq_control);
+            step_cpu_cycles(&mut mapper, cycles - 1);
+            assert!(!mapper.is_irq());
+            step_cpu_cycles(&mut mapper, 1);
+            assert!(mapper.is_irq());
+            //The bits above the counter size never move
+            assert_eq!(mapper.irq_counter, upper_bits);
+            //And it keeps going round inside its own bits
+            step_cpu_cycles(&mut mapper, 1);
+            assert_eq!(mapper.irq_counter, upper_bits | mapper.irq_mask);
+        }
+    }
+
+    #[test]
+    fn irq_control_acknowledges() {
+        let mut mapper = new_mapper();
+        start_irq(&mut mapper, 0x08);
+        step_cpu_cycles(&mut mapper, 4);
+        assert!(mapper.is_irq());
+        mapper.write_u8(0xf001, 0x00, false);
+        assert!(!mapper.is_irq());
+        //Disabled, nothing counts
+        step_cpu_cycles(&mut mapper, 0x100);
+        assert!(!mapper.is_irq());
+        assert_eq!(mapper.irq_counter, 0x1230);
+    }
+}
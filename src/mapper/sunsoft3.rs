@@ -0,0 +1,175 @@
+/* Sunsoft-3, mapper 67 */
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_067
+//One switchable 16k PRG bank with the last fixed, four 2k CHR banks, mirroring control and a 16-bit IRQ counter
+//that counts CPU cycles. Fantasy Zone II (JP), Mito Koumon II. Registers are picked by addr & $F800:
+//  $8800-$B800: CHR banks, $C800: IRQ counter (high byte then low), $D800: IRQ enable, $E800: mirroring, $F800: PRG bank
+
+use super::Mapper;
+use crate::rom::*;
+
+pub const SUNSOFT3_PRG_BANK_SIZE: usize = 0x4000;
+pub const SUNSOFT3_CHR_BANK_SIZE: usize = 0x0800;
+
+#[derive(Debug)]
+pub struct Sunsoft3 {
+    data: RomData,
+    prg_bank: u8,
+    chr_banks: [u8; 4],
+    mirroring: u8,
+    irq_counter: u16,
+    //$C800 takes two writes, high byte first. $D800 puts it back to the high byte
+    is_irq_low_byte: bool,
+    is_irq_enable: bool,
+    is_irq: bool,
+}
+
+impl Sunsoft3 {
+    pub fn new(data: RomData) -> Self {
+        Self {
+            data,
+            prg_bank: 0,
+            chr_banks: [0; 4],
+            mirroring: 0,
+            irq_counter: 0,
+            is_irq_low_byte: false,
+            is_irq_enable: false,
+            is_irq: false,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / SUNSOFT3_PRG_BANK_SIZE;
+        let bank = if addr < 0xc000 {
+            usize::from(self.prg_bank)
+        } else {
+            num_of_banks.saturating_sub(1)
+        };
+        (bank * SUNSOFT3_PRG_BANK_SIZE + (usize::from(addr) & (SUNSOFT3_PRG_BANK_SIZE - 1))) % self.data.p_rom.len()
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = usize::from(self.chr_banks[usize::from(addr >> 11) & 0x03]);
+        bank * SUNSOFT3_CHR_BANK_SIZE + (usize::from(addr) & (SUNSOFT3_CHR_BANK_SIZE - 1))
+    }
+}
+
+impl Mapper for Sunsoft3 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+            return;
+        }
+        match addr & 0xf800 {
+            0x8800 => self.chr_banks[0] = data,
+            0x9800 => self.chr_banks[1] = data,
+            0xa800 => self.chr_banks[2] = data,
+            0xb800 => self.chr_banks[3] = data,
+            0xc800 => {
+                self.irq_counter = if self.is_irq_low_byte {
+                    (self.irq_counter & 0xff00) | u16::from(data)
+                } else {
+                    (self.irq_counter & 0x00ff) | (u16::from(data) << 8)
+                };
+                self.is_irq_low_byte = !self.is_irq_low_byte;
+            }
+            0xd800 => {
+                self.is_irq_enable = (data & 0x10) == 0x10;
+                self.is_irq_low_byte = false;
+                self.is_irq = false;
+            }
+            0xe800 => self.mirroring = data & 0x03,
+            0xf800 => self.prg_bank = data,
+            _ => {}
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.mirroring {
+            0 => MirrorTable::Vertical,
+            1 => MirrorTable::Horizontal,
+            2 => MirrorTable::SingleScreenLower,
+            _ => MirrorTable::SingleScreenUpper,
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.is_irq
+    }
+    fn step_cpu_cycle(&mut self) {
+        if !self.is_irq_enable {
+            return;
+        }
+        //Going past 0 fires the IRQ and stops the counter until it's enabled again
+        if self.irq_counter == 0 {
+            self.is_irq_enable = false;
+            self.is_irq = true;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_mapper() -> Sunsoft3 {
+        let p_rom = (0..8).flat_map(|bank| vec![bank as u8; SUNSOFT3_PRG_BANK_SIZE]).collect();
+        Sunsoft3::new(RomData { mapper_id: 67, p_rom, ..RomData::default() })
+    }
+    fn step_cpu_cycles(mapper: &mut Sunsoft3, count: usize) {
+        for _ in 0..count {
+            mapper.step_cpu_cycle();
+        }
+    }
+
+    #[test]
+    fn irq_counter_write_order() {
+        let mut mapper = new_mapper();
+        mapper.write_u8(0xc800, 0x12, false);
+        mapper.write_u8(0xc800, 0x34, false);
+        assert_eq!(mapper.irq_counter, 0x1234);
+        //$D800 puts the next write back on the high byte
+        mapper.write_u8(0xc800, 0x56, false);
+        mapper.write_u8(0xd800, 0x00, false);
+        mapper.write_u8(0xc800, 0x78, false);
+        assert_eq!(mapper.irq_counter, 0x7834);
+    }
+
+    #[test]
+    fn irq_wraps_and_stops() {
+        let mut mapper = new_mapper();
+        mapper.write_u8(0xc800, 0x00, false);
+        mapper.write_u8(0xc800, 0x10, false);
+        mapper.write_u8(0xd800, 0x10, false);
+        //Counts down to 0, the IRQ comes on the step past it
+        step_cpu_cycles(&mut mapper, 0x10);
+        assert!(!mapper.is_irq());
+        assert_eq!(mapper.irq_counter, 0x0000);
+        step_cpu_cycles(&mut mapper, 1);
+        assert!(mapper.is_irq());
+        assert_eq!(mapper.irq_counter, 0xffff);
+        //Stopped until it's enabled again
+        step_cpu_cycles(&mut mapper, 0x100);
+        assert_eq!(mapper.irq_counter, 0xffff);
+        //$D800 acknowledges
+        mapper.write_u8(0xd800, 0x00, false);
+        assert!(!mapper.is_irq());
+        step_cpu_cycles(&mut mapper, 0x100);
+        assert_eq!(mapper.irq_counter, 0xffff);
+    }
+}
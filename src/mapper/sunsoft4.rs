@@ -0,0 +1,241 @@
+/* Sunsoft-4, mapper 68 */
+//http://wiki.nesdev.com/w/index.php/INES_Mapper_068
+//One switchable 16k PRG bank with the last fixed and four 2k CHR banks, plus the trick After Burner is built around:
+//the nametables can be pointed at 1k pages of CHR-ROM, which is how it fits its big scrolling backgrounds.
+//  $8000-$B000: CHR banks, $C000/$D000: nametable ROM pages, $E000: mirroring and ROM nametable enable, $F000: PRG bank and RAM enable
+//The RAM at $6000 only cares about $F000 bit 4.
+//Nantettatte!! Baseball (NES 2.0 submapper 1) has a second cartridge plugged into the top of it with an option ROM. There $F000
+//bit 3 clear swaps $8000-$BFFF over to the option ROM, which dumps append after the 128k of internal ROM. A licensing timer
+//started by writes to $6000-$7FFF cuts the option ROM off again after about 60ms, and with it gone $8000-$BFFF is open bus.
+//Everything else has no option ROM and bit 3 is just another PRG bank bit.
+
+use super::Mapper;
+use crate::rom::*;
+use crate::video::*;
+
+pub const SUNSOFT4_PRG_BANK_SIZE: usize = 0x4000;
+pub const SUNSOFT4_CHR_BANK_SIZE: usize = 0x0800;
+pub const SUNSOFT4_NAME_TABLE_BANK_SIZE: usize = 0x0400;
+pub const SUNSOFT4_INTERNAL_ROM_SIZE: usize = 0x20000;
+//How long the option ROM stays on after a write to $6000-$7FFF, in CPU cycles (about 60ms)
+pub const SUNSOFT4_LICENSING_TIMER: u32 = 107520;
+
+#[derive(Debug)]
+pub struct Sunsoft4 {
+    data: RomData,
+    //$F000 bits 0-2
+    prg_bank: u8,
+    //$F000 bit 3, clear picks the option ROM on boards that have one
+    is_internal_rom: bool,
+    is_option_rom_board: bool,
+    licensing_timer: u32,
+    chr_banks: [u8; 4],
+    //$C000 and $D000, only the low 7 bits are stored since the chip always sets the top one
+    nametable_banks: [u8; 2],
+    //$E000, bits 0-1 mirroring, bit 4 nametables from CHR-ROM
+    control: u8,
+    //$F000 bit 4
+    is_ram_enable: bool,
+}
+
+impl Sunsoft4 {
+    pub fn new(data: RomData) -> Self {
+        let is_option_rom_board = data.submapper_id == 1;
+        Self {
+            data,
+            prg_bank: 0,
+            is_internal_rom: false,
+            is_option_rom_board,
+            licensing_timer: 0,
+            chr_banks: [0; 4],
+            nametable_banks: [0; 2],
+            control: 0,
+            is_ram_enable: false,
+        }
+    }
+    //None when $8000-$BFFF points at an option ROM that isn't there or has been locked out
+    fn prg_rom_index(&self, addr: u16) -> Option<usize> {
+        let offset = usize::from(addr) & (SUNSOFT4_PRG_BANK_SIZE - 1);
+        if !self.is_option_rom_board {
+            let num_of_banks = self.data.p_rom.len() / SUNSOFT4_PRG_BANK_SIZE;
+            let bank = if addr < 0xc000 {
+                usize::from(self.prg_bank) | if self.is_internal_rom { 0x08 } else { 0x00 }
+            } else {
+                num_of_banks.saturating_sub(1)
+            };
+            return Some((bank * SUNSOFT4_PRG_BANK_SIZE + offset) % self.data.p_rom.len());
+        }
+        let internal_size = self.data.p_rom.len().min(SUNSOFT4_INTERNAL_ROM_SIZE);
+        if addr >= 0xc000 {
+            Some(internal_size - SUNSOFT4_PRG_BANK_SIZE + offset)
+        } else if self.is_internal_rom {
+            Some((usize::from(self.prg_bank) * SUNSOFT4_PRG_BANK_SIZE + offset) % internal_size)
+        } else {
+            let option_size = self.data.p_rom.len() - internal_size;
+            if option_size == 0 || self.licensing_timer == 0 {
+                None
+            } else {
+                Some(internal_size + (usize::from(self.prg_bank) * SUNSOFT4_PRG_BANK_SIZE + offset) % option_size)
+            }
+        }
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = usize::from(self.chr_banks[usize::from(addr >> 11) & 0x03]);
+        bank * SUNSOFT4_CHR_BANK_SIZE + (usize::from(addr) & (SUNSOFT4_CHR_BANK_SIZE - 1))
+    }
+    fn is_rom_nametables(&self) -> bool {
+        (self.control & 0x10) == 0x10
+    }
+    //CHR-ROM index for a nametable address. The mirroring setting still decides which of the two pages each nametable gets
+    fn nametable_rom_index(&self, addr: u16) -> usize {
+        let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+        let bank = usize::from(self.nametable_banks[page]) | 0x80;
+        bank * SUNSOFT4_NAME_TABLE_BANK_SIZE + offset
+    }
+}
+
+impl Mapper for Sunsoft4 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_ram_enable {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index]
+            } else {
+                open_bus(addr)
+            }
+        } else {
+            match self.prg_rom_index(addr) {
+                Some(index) => self.data.p_rom[index],
+                None => open_bus(addr),
+            }
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if is_nondestructive {
+            return;
+        }
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            self.licensing_timer = SUNSOFT4_LICENSING_TIMER;
+            if self.is_ram_enable {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index] = data;
+            }
+            return;
+        }
+        match addr & 0xf000 {
+            0x8000 => self.chr_banks[0] = data,
+            0x9000 => self.chr_banks[1] = data,
+            0xa000 => self.chr_banks[2] = data,
+            0xb000 => self.chr_banks[3] = data,
+            0xc000 => self.nametable_banks[0] = data & 0x7f,
+            0xd000 => self.nametable_banks[1] = data & 0x7f,
+            0xe000 => self.control = data,
+            _ => {
+                self.prg_bank = data & 0x07;
+                self.is_internal_rom = (data & 0x08) == 0x08;
+                self.is_ram_enable = (data & 0x10) == 0x10;
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.control & 0x03 {
+            0 => MirrorTable::Vertical,
+            1 => MirrorTable::Horizontal,
+            2 => MirrorTable::SingleScreenLower,
+            _ => MirrorTable::SingleScreenUpper,
+        }
+    }
+    fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        if self.is_rom_nametables() {
+            self.data.read_chr(self.nametable_rom_index(addr))
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset]
+        }
+    }
+    fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        //It's ROM, so writes only land when the console's nametables are mapped in
+        if !self.is_rom_nametables() {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset] = data;
+        }
+    }
+    fn step_cpu_cycle(&mut self) {
+        self.licensing_timer = self.licensing_timer.saturating_sub(1);
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.data.battery_save_data()
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.data.load_battery_save_data(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //128k of internal ROM plus a 32k option ROM, every byte of a 16k bank holds that bank's number
+    fn new_mapper(submapper_id: u8) -> Sunsoft4 {
+        let p_rom = (0..10).flat_map(|bank| vec![bank as u8; SUNSOFT4_PRG_BANK_SIZE]).collect();
+        Sunsoft4::new(RomData { mapper_id: 68, submapper_id, p_rom, ..RomData::default() })
+    }
+
+    #[test]
+    fn option_rom_licensing_timer() {
+        let mut mapper = new_mapper(1);
+        mapper.write_u8(0xf000, 0x09, false);
+        assert_eq!(mapper.read_u8(0x8000, false), 1);
+        assert_eq!(mapper.read_u8(0xc000, false), 7);
+        //Option ROM picked but the timer hasn't been started
+        mapper.write_u8(0xf000, 0x01, false);
+        assert_eq!(mapper.read_u8(0x8000, false), 0x80);
+        mapper.write_u8(0x6000, 0x00, false);
+        assert_eq!(mapper.read_u8(0x8000, false), 9);
+        assert_eq!(mapper.read_u8(0xc000, false), 7);
+        for _ in 0..SUNSOFT4_LICENSING_TIMER - 1 {
+            mapper.step_cpu_cycle();
+        }
+        assert_eq!(mapper.read_u8(0xbfff, false), 9);
+        mapper.step_cpu_cycle();
+        assert_eq!(mapper.read_u8(0xbfff, false), 0xbf);
+        //The internal ROM doesn't care about the timer
+        mapper.write_u8(0xf000, 0x0a, false);
+        assert_eq!(mapper.read_u8(0x8000, false), 2);
+        //And writing $6000 again brings the option ROM back
+        mapper.write_u8(0xf000, 0x00, false);
+        mapper.write_u8(0x7fff, 0x00, false);
+        assert_eq!(mapper.read_u8(0x8000, false), 8);
+    }
+
+    #[test]
+    fn ram_ignores_licensing_timer() {
+        let mut mapper = new_mapper(1);
+        mapper.write_u8(0xf000, 0x10, false);
+        mapper.write_u8(0x6000, 0x5a, false);
+        for _ in 0..SUNSOFT4_LICENSING_TIMER {
+            mapper.step_cpu_cycle();
+        }
+        assert_eq!(mapper.read_u8(0x6000, false), 0x5a);
+        mapper.write_u8(0xf000, 0x00, false);
+        assert_eq!(mapper.read_u8(0x6000, false), 0x60);
+    }
+
+    #[test]
+    fn no_option_rom_board() {
+        let mut mapper = new_mapper(0);
+        //Bit 3 is just PRG A17 here
+        mapper.write_u8(0xf000, 0x01, false);
+        assert_eq!(mapper.read_u8(0x8000, false), 1);
+        mapper.write_u8(0xf000, 0x09, false);
+        assert_eq!(mapper.read_u8(0x8000, false), 9);
+        assert_eq!(mapper.read_u8(0xc000, false), 9);
+    }
+}
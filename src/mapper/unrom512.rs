@@ -0,0 +1,128 @@
+/* UNROM 512, mapper 30 */
+//http://wiki.nesdev.com/w/index.php/UNROM_512
+//RetroUSB's homebrew board. UNROM with up to 512k of PRG, 32k of CHR RAM in four 8k banks, and a bit for single screen mirroring,
+//all from one latch: PPPPP is the PRG bank at $8000, CC the CHR bank, M the screen.
+//  MCCPPPPP
+//The header's mirroring bits pick the variant: horizontal, vertical, single screen under the game's control, or four screen
+//using the last 8k of CHR RAM for nametables. With the battery bit set the PRG is an SST39SF040 the game can rewrite to save,
+//the latch moves up to $C000-$FFFF and writes to $8000-$BFFF go to the flash instead.
+
+use super::bank::*;
+use super::flash::Flash;
+use super::Mapper;
+use crate::rom::*;
+use crate::video::*;
+
+pub const UNROM512_PRG_BANK_SIZE: usize = 0x4000;
+pub const UNROM512_CHR_BANK_SIZE: usize = 0x2000;
+pub const UNROM512_CHR_RAM_SIZE: usize = 0x8000;
+//Four screen nametables live in the last CHR RAM bank
+pub const UNROM512_NAME_TABLE_BASE: usize = 0x6000;
+
+#[derive(Debug)]
+pub struct Unrom512 {
+    data: RomData,
+    latch: BankLatch,
+    //Only on the self flashing board
+    flash: Option<Flash>,
+}
+
+impl Unrom512 {
+    pub fn new(mut data: RomData) -> Self {
+        //Old headers ask for 8k of CHR RAM, the board always has 32k
+        if data.is_chr_ram() && data.c_ram.len() < UNROM512_CHR_RAM_SIZE {
+            data.c_ram.resize(UNROM512_CHR_RAM_SIZE, 0);
+        }
+        let flash = if data.sram {
+            Some(Flash::new(data.p_rom.len()))
+        } else {
+            None
+        };
+        //The flash board decodes its latch properly, the plain one has the usual bus conflicts
+        let latch = BankLatch::new(flash.is_none());
+        Self { data, latch, flash }
+    }
+    fn prg_bank(&self, addr: u16) -> usize {
+        if addr >= 0xc000 {
+            (self.data.p_rom.len() / UNROM512_PRG_BANK_SIZE).saturating_sub(1)
+        } else {
+            usize::from(self.latch.value & 0x1f)
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        bank_index(self.prg_bank(addr), UNROM512_PRG_BANK_SIZE, addr, self.data.p_rom.len())
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = usize::from((self.latch.value >> 5) & 0x03);
+        bank * UNROM512_CHR_BANK_SIZE + (usize::from(addr) & (UNROM512_CHR_BANK_SIZE - 1))
+    }
+    fn is_four_screen(&self) -> bool {
+        matches!(self.mirror_table(), MirrorTable::FourScreen)
+    }
+}
+
+impl Mapper for Unrom512 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            //No RAM on this board, open bus
+            open_bus(addr)
+        } else {
+            let index = self.prg_rom_index(addr);
+            match &self.flash {
+                Some(flash) => flash.read(&self.data.p_rom, index),
+                None => self.data.p_rom[index],
+            }
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            //Nothing here to write to
+        } else if !is_nondestructive {
+            let index = self.prg_rom_index(addr);
+            match &mut self.flash {
+                Some(flash) if addr < 0xc000 => flash.write(&mut self.data.p_rom, index, data),
+                _ => self.latch.write(data, self.data.p_rom[index]),
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match (self.data.is_alt_nametables, self.data.mirror_table) {
+            (true, MirrorTable::Horizontal) if (self.latch.value & 0x80) == 0x80 => MirrorTable::SingleScreenUpper,
+            (true, MirrorTable::Horizontal) => MirrorTable::SingleScreenLower,
+            (true, _) => MirrorTable::FourScreen,
+            (false, mirror_table) => mirror_table,
+        }
+    }
+    fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        if self.is_four_screen() {
+            self.data.read_chr_ram(UNROM512_NAME_TABLE_BASE + (usize::from(addr) & 0x0fff))
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset]
+        }
+    }
+    fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        if self.is_four_screen() {
+            self.data.write_chr_ram(UNROM512_NAME_TABLE_BASE + (usize::from(addr) & 0x0fff), data);
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset] = data;
+        }
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        //Only the sectors the game has rewritten, the rest is already in the ROM file
+        self.flash.as_ref().map(|flash| flash.save_data(&self.data.p_rom))
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(flash) = &mut self.flash {
+            flash.load_save_data(&mut self.data.p_rom, data);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+/* UxROM, mappers 2, 94 and 180 */
+//http://wiki.nesdev.com/w/index.php/UxROM
+//One switchable 16k PRG bank and one fixed, with 8k of CHR RAM. Mega Man, Castlevania and Contra live here.
+//The bank register is just a latch hanging off the data bus, so the ROM drives the bus during the write as well
+//and the latch sees both values ANDed together (a bus conflict). Games avoid trouble by writing a byte that matches the ROM.
+
+use super::Mapper;
+use crate::rom::*;
+
+pub const UXROM_PRG_BANK_SIZE: usize = 0x4000;
+
+#[derive(Copy, Clone, Debug)]
+pub enum UxromBoard {
+    //Mapper 2, UNROM/UOROM, switchable bank at $8000, last bank fixed at $C000
+    Unrom,
+    //Mapper 94, UN1ROM, same layout but the bank number sits in bits 2-4 (Senjou no Ookami)
+    Un1rom,
+    //Mapper 180, UNROM wired backwards, first bank fixed at $8000, switchable bank at $C000 (Crazy Climber)
+    UnromFixedFirst,
+}
+
+#[derive(Debug)]
+pub struct Uxrom {
+    data: RomData,
+    board: UxromBoard,
+    prg_bank: u8,
+    //NES 2.0 submapper 1 says the board has no bus conflicts, 2 (and old dumps) that it ANDs like the rest
+    is_bus_conflict: bool,
+}
+
+impl Uxrom {
+    pub fn new(data: RomData, board: UxromBoard) -> Self {
+        let is_bus_conflict = !matches!(board, UxromBoard::Unrom) || data.submapper_id != 1;
+        Self {
+            data,
+            board,
+            prg_bank: 0,
+            is_bus_conflict,
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let last_bank = (self.data.p_rom.len() / UXROM_PRG_BANK_SIZE).saturating_sub(1);
+        let is_upper = addr >= 0xc000;
+        let bank = match (self.board, is_upper) {
+            (UxromBoard::UnromFixedFirst, false) => 0,
+            (UxromBoard::UnromFixedFirst, true) => usize::from(self.prg_bank),
+            (_, false) => usize::from(self.prg_bank),
+            (_, true) => last_bank,
+        };
+        let offset = usize::from(addr) & (UXROM_PRG_BANK_SIZE - 1);
+        (bank * UXROM_PRG_BANK_SIZE + offset) % self.data.p_rom.len()
+    }
+}
+
+impl Mapper for Uxrom {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+        } else if !is_nondestructive {
+            //Bus conflict, the latch gets whatever the ROM and the CPU agree on
+            let latch = if self.is_bus_conflict {
+                data & self.data.p_rom[self.prg_rom_index(addr)]
+            } else {
+                data
+            };
+            self.prg_bank = match self.board {
+                UxromBoard::Un1rom => (latch >> 2) & 0x07,
+                _ => latch,
+            };
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(usize::from(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        self.data.write_chr(usize::from(addr), data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        self.data.header_mirror_table()
+    }
+}
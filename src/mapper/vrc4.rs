@@ -0,0 +1,292 @@
+/* Konami VRC2 and VRC4, mappers 21, 22, 23 and 25 */
+//http://wiki.nesdev.com/w/index.php/VRC2_and_VRC4
+//Two switchable 8k PRG banks, eight 1k CHR banks written a nibble at a time, and (VRC4 only) the VRC IRQ counter.
+//Every register has four addresses, picked by two of the CPU address lines. Which two depends on how the board was wired,
+//and that's most of why there are four mapper numbers for what is basically one chip:
+//  21: VRC4a (A1, A2)   VRC4c (A6, A7)
+//  22: VRC2a (A1, A0), CHR banks are in 2k units
+//  23: VRC4f (A0, A1)   VRC4e (A2, A3)   VRC2b (A0, A1)
+//  25: VRC4b (A1, A0)   VRC4d (A3, A2)   VRC2c (A1, A0)
+//NES 2.0 submappers pin the exact wiring. Without one we listen on both sets of lines, no game writes to the other pair by accident.
+
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::rom::*;
+
+pub const VRC4_PRG_BANK_SIZE: usize = 0x2000;
+pub const VRC4_CHR_BANK_SIZE: usize = 0x0400;
+
+#[derive(Debug)]
+pub struct Vrc4 {
+    data: RomData,
+    //VRC2 has no IRQ, no PRG swap mode and only horizontal/vertical mirroring
+    is_vrc2: bool,
+    //VRC2a drops the lowest bit of the CHR bank numbers
+    is_chr_shifted: bool,
+    //Which CPU address bits act as the chip's A0 and A1
+    a0_mask: u16,
+    a1_mask: u16,
+    prg_banks: [u8; 2],
+    //$9002 bit 1, swaps $8000 and $C000
+    is_prg_swap: bool,
+    chr_banks: [u16; 8],
+    mirroring: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    pub fn new(data: RomData) -> Self {
+        let (a0_mask, a1_mask, is_vrc2) = match (data.mapper_id, data.submapper_id) {
+            (21, 1) => (0x02, 0x04, false),
+            (21, 2) => (0x40, 0x80, false),
+            (21, _) => (0x42, 0x84, false),
+            (22, _) => (0x02, 0x01, true),
+            (23, 1) => (0x01, 0x02, false),
+            (23, 2) => (0x04, 0x08, false),
+            (23, 3) => (0x01, 0x02, true),
+            (23, _) => (0x05, 0x0a, false),
+            (25, 1) => (0x02, 0x01, false),
+            (25, 2) => (0x08, 0x04, false),
+            (25, 3) => (0x02, 0x01, true),
+            (_, _) => (0x0a, 0x05, false),
+        };
+        let is_chr_shifted = data.mapper_id == 22;
+        Self {
+            data,
+            is_vrc2,
+            is_chr_shifted,
+            a0_mask,
+            a1_mask,
+            prg_banks: [0; 2],
+            is_prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: 0,
+            irq: VrcIrq::default(),
+        }
+    }
+    //Folds whatever wiring the board has down to $x000-$x003
+    fn register_addr(&self, addr: u16) -> u16 {
+        let mut reg = addr & 0xf000;
+        if (addr & self.a0_mask) != 0 {
+            reg |= 0x01;
+        }
+        if (addr & self.a1_mask) != 0 {
+            reg |= 0x02;
+        }
+        reg
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / VRC4_PRG_BANK_SIZE;
+        let second_last_bank = num_of_banks.saturating_sub(2);
+        let last_bank = num_of_banks.saturating_sub(1);
+        let bank = match ((addr >> 13) & 0x03, self.is_prg_swap) {
+            (0, false) => usize::from(self.prg_banks[0]),
+            (0, true) => second_last_bank,
+            (1, _) => usize::from(self.prg_banks[1]),
+            (2, false) => second_last_bank,
+            (2, true) => usize::from(self.prg_banks[0]),
+            _ => last_bank,
+        };
+        let offset = usize::from(addr) & (VRC4_PRG_BANK_SIZE - 1);
+        (bank * VRC4_PRG_BANK_SIZE + offset) % self.data.p_rom.len()
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let slot = usize::from(addr >> 10) & 0x07;
+        let bank = if self.is_chr_shifted {
+            usize::from(self.chr_banks[slot] >> 1)
+        } else {
+            usize::from(self.chr_banks[slot])
+        };
+        bank * VRC4_CHR_BANK_SIZE + (usize::from(addr) & (VRC4_CHR_BANK_SIZE - 1))
+    }
+    fn write_chr_bank(&mut self, reg: u16, data: u8) {
+        //$B000-$E003, two banks per 4k of address space, each written as a low and a high nibble
+        let index = usize::from((reg - 0xb000) >> 12) * 2 + usize::from((reg >> 1) & 0x01);
+        let bank = self.chr_banks[index];
+        self.chr_banks[index] = if (reg & 0x01) == 0x00 {
+            (bank & 0x1f0) | u16::from(data & 0x0f)
+        } else {
+            //VRC4 has 9 bits of CHR bank, VRC2 only 8
+            let mask = if self.is_vrc2 { 0x0f } else { 0x1f };
+            (bank & 0x0f) | (u16::from(data & mask) << 4)
+        };
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index]
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            //VRC2 boards without RAM have a 1 bit latch here instead, reading back what was written covers both
+            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+            self.data.srambytes[index] = data;
+        } else if !is_nondestructive {
+            let reg = self.register_addr(addr);
+            match reg {
+                0x8000..=0x8003 => self.prg_banks[0] = data & 0x1f,
+                0x9000..=0x9003 if self.is_vrc2 => self.mirroring = data & 0x01,
+                0x9000 | 0x9001 => self.mirroring = data & 0x03,
+                0x9002 => self.is_prg_swap = (data & 0x02) == 0x02,
+                0xa000..=0xa003 => self.prg_banks[1] = data & 0x1f,
+                0xb000..=0xe003 => self.write_chr_bank(reg, data),
+                0xf000..=0xf003 if self.is_vrc2 => {}
+                0xf000 => self.irq.latch = (self.irq.latch & 0xf0) | (data & 0x0f),
+                0xf001 => self.irq.latch = (self.irq.latch & 0x0f) | ((data & 0x0f) << 4),
+                0xf002 => self.irq.write_control(data),
+                0xf003 => self.irq.acknowledge(),
+                _ => {}
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.mirroring {
+            0 => MirrorTable::Vertical,
+            1 => MirrorTable::Horizontal,
+            2 => MirrorTable::SingleScreenLower,
+            _ => MirrorTable::SingleScreenUpper,
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.irq.is_irq()
+    }
+    fn step_cpu_cycle(&mut self) {
+        self.irq.step_cpu_cycle();
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.data.battery_save_data()
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.data.load_battery_save_data(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //256k PRG and 256k CHR where every byte of a bank holds that bank's number
+    fn new_mapper(mapper_id: u16, submapper_id: u8) -> Vrc4 {
+        let p_rom = (0..32).flat_map(|bank| vec![bank as u8; VRC4_PRG_BANK_SIZE]).collect();
+        let c_rom = (0..256).flat_map(|bank| vec![bank as u8; VRC4_CHR_BANK_SIZE]).collect();
+        Vrc4::new(RomData { mapper_id, submapper_id, p_rom, c_rom, ..RomData::default() })
+    }
+
+    //(mapper, submapper, CPU line for the chip's A0, CPU line for A1, is VRC2)
+    const WIRINGS: [(u16, u8, u16, u16, bool); 9] = [
+        (21, 1, 0x02, 0x04, false),
+        (21, 2, 0x40, 0x80, false),
+        (22, 0, 0x02, 0x01, true),
+        (23, 1, 0x01, 0x02, false),
+        (23, 2, 0x04, 0x08, false),
+        (23, 3, 0x01, 0x02, true),
+        (25, 1, 0x02, 0x01, false),
+        (25, 2, 0x08, 0x04, false),
+        (25, 3, 0x02, 0x01, true),
+    ];
+
+    #[test]
+    fn submapper_wiring() {
+        for (mapper_id, submapper_id, a0, a1, is_vrc2) in WIRINGS {
+            let mut mapper = new_mapper(mapper_id, submapper_id);
+            //$C002/$C003 are the low and high nibble of the bank at $0C00
+            mapper.write_u8(0xc000 | a1, 0x05, false);
+            mapper.write_u8(0xc000 | a1 | a0, 0x03, false);
+            let expected = if mapper_id == 22 { 0x35 >> 1 } else { 0x35 };
+            assert_eq!(mapper.read_video_u8(0x0c00), expected, "mapper {} submapper {}", mapper_id, submapper_id);
+            //$C000 didn't move
+            assert_eq!(mapper.read_video_u8(0x0800), 0x00);
+            //$9002 is the PRG swap on VRC4, VRC2 only has mirroring there
+            mapper.write_u8(0x8000, 0x07, false);
+            mapper.write_u8(0x9000 | a1, 0x02, false);
+            let expected = if is_vrc2 { [7, 30] } else { [30, 7] };
+            assert_eq!([mapper.read_u8(0x8000, false), mapper.read_u8(0xc000, false)], expected);
+        }
+    }
+
+    #[test]
+    fn no_submapper_listens_on_both() {
+        //VRC4a lines
+        let mut mapper = new_mapper(21, 0);
+        mapper.write_u8(0xb004, 0x02, false);
+        mapper.write_u8(0xb006, 0x01, false);
+        assert_eq!(mapper.read_video_u8(0x0400), 0x12);
+        //VRC4c lines
+        let mut mapper = new_mapper(21, 0);
+        mapper.write_u8(0xb080, 0x04, false);
+        mapper.write_u8(0xb0c0, 0x01, false);
+        assert_eq!(mapper.read_video_u8(0x0400), 0x14);
+    }
+
+    #[test]
+    fn irq_cycle_mode() {
+        let mut mapper = new_mapper(21, 1);
+        mapper.write_u8(0xf000, 0x0d, false);
+        mapper.write_u8(0xf002, 0x0f, false);
+        //Enabled in cycle mode, $FD counts up to $FF and fires on the next one
+        mapper.write_u8(0xf004, 0x06, false);
+        for _ in 0..2 {
+            mapper.step_cpu_cycle();
+            assert!(!mapper.is_irq());
+        }
+        mapper.step_cpu_cycle();
+        assert!(mapper.is_irq());
+        //Acknowledge with A clear stops the counter
+        mapper.write_u8(0xf006, 0x00, false);
+        assert!(!mapper.is_irq());
+        for _ in 0..0x200 {
+            mapper.step_cpu_cycle();
+        }
+        assert!(!mapper.is_irq());
+    }
+
+    #[test]
+    fn irq_scanline_mode() {
+        let mut mapper = new_mapper(21, 1);
+        mapper.write_u8(0xf000, 0x0f, false);
+        mapper.write_u8(0xf002, 0x0f, false);
+        //A set so it keeps going after the acknowledge
+        mapper.write_u8(0xf004, 0x03, false);
+        //A scanline is 341/3 CPU cycles, so the first one lands on the 114th
+        for _ in 0..113 {
+            mapper.step_cpu_cycle();
+        }
+        assert!(!mapper.is_irq());
+        mapper.step_cpu_cycle();
+        assert!(mapper.is_irq());
+        mapper.write_u8(0xf006, 0x00, false);
+        assert!(!mapper.is_irq());
+        //Then reloads from the latch and keeps going, it averages out to 113.667
+        for _ in 0..113 {
+            mapper.step_cpu_cycle();
+        }
+        assert!(!mapper.is_irq());
+        mapper.step_cpu_cycle();
+        assert!(mapper.is_irq());
+    }
+
+    #[test]
+    fn vrc2_has_no_irq() {
+        let mut mapper = new_mapper(22, 0);
+        mapper.write_u8(0xf000, 0x0f, false);
+        mapper.write_u8(0xf002, 0x0f, false);
+        mapper.write_u8(0xf001, 0x06, false);
+        for _ in 0..0x200 {
+            mapper.step_cpu_cycle();
+        }
+        assert!(!mapper.is_irq());
+    }
+}
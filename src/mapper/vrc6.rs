@@ -0,0 +1,306 @@
+/* Konami VRC6, mappers 24 and 26 */
+//http://wiki.nesdev.com/w/index.php/VRC6
+//A 16k and an 8k switchable PRG bank, eight 1k CHR banks with a few layouts, the VRC IRQ counter, and three extra sound channels:
+//two pulses with 8 duty settings and a sawtooth.
+//Akumajou Densetsu is mapper 24, Madara and Esper Dream 2 are mapper 26 which swaps the A0 and A1 lines going into the chip.
+//http://wiki.nesdev.com/w/index.php/VRC6_audio
+
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::rom::*;
+use crate::video::*;
+
+pub const VRC6_PRG_BANK_SIZE: usize = 0x2000;
+pub const VRC6_CHR_BANK_SIZE: usize = 0x0400;
+
+#[derive(Clone, Debug, Default)]
+pub struct Vrc6Pulse {
+    //$9000/$A000, mode, duty and volume
+    control: u8,
+    //$9001-$9002/$A001-$A002, 12 bits
+    period: u16,
+    is_enable: bool,
+    timer: u16,
+    duty_step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write_reg(&mut self, index: u16, data: u8) {
+        match index {
+            0 => self.control = data,
+            1 => self.period = (self.period & 0x0f00) | u16::from(data),
+            _ => {
+                self.period = (self.period & 0x00ff) | (u16::from(data & 0x0f) << 8);
+                self.is_enable = (data & 0x80) == 0x80;
+                //Turning the channel off resets where it is in the waveform
+                if !self.is_enable {
+                    self.duty_step = 15;
+                }
+            }
+        }
+    }
+    fn step_timer(&mut self, shift: u8) {
+        if !self.is_enable {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.duty_step = if self.duty_step == 0 { 15 } else { self.duty_step - 1 };
+        } else {
+            self.timer -= 1;
+        }
+    }
+    fn output(&self) -> u8 {
+        let is_digitized = (self.control & 0x80) == 0x80;
+        let duty = (self.control >> 4) & 0x07;
+        if self.is_enable && (is_digitized || self.duty_step <= duty) {
+            self.control & 0x0f
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Vrc6Sawtooth {
+    //$B000, bits 0-5 get added to the accumulator every other timer clock
+    rate: u8,
+    //$B001-$B002
+    period: u16,
+    is_enable: bool,
+    timer: u16,
+    //Counts timer clocks, the accumulator resets after the 14th
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Sawtooth {
+    fn write_reg(&mut self, index: u16, data: u8) {
+        match index {
+            0 => self.rate = data & 0x3f,
+            1 => self.period = (self.period & 0x0f00) | u16::from(data),
+            _ => {
+                self.period = (self.period & 0x00ff) | (u16::from(data & 0x0f) << 8);
+                self.is_enable = (data & 0x80) == 0x80;
+                if !self.is_enable {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+    fn step_timer(&mut self, shift: u8) {
+        if !self.is_enable {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step += 1;
+            if self.step == 14 {
+                self.step = 0;
+                self.accumulator = 0;
+            } else if (self.step & 0x01) == 0x00 {
+                self.accumulator = self.accumulator.wrapping_add(self.rate);
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+    fn output(&self) -> u8 {
+        //Only the top 5 bits make it out
+        self.accumulator >> 3
+    }
+}
+
+#[derive(Debug)]
+pub struct Vrc6 {
+    data: RomData,
+    //Mapper 26 has A0 and A1 the other way round
+    is_swapped_lines: bool,
+    //$8000, 16k bank
+    prg_bank_16k: u8,
+    //$C000, 8k bank
+    prg_bank_8k: u8,
+    //$D000-$E003, R0-R7
+    chr_banks: [u8; 8],
+    //$B003, CHR layout, mirroring, nametable source and RAM enable
+    banking_control: u8,
+    //$9003, halt and frequency scaling for all three channels
+    audio_control: u8,
+    pulses: [Vrc6Pulse; 2],
+    sawtooth: Vrc6Sawtooth,
+    irq: VrcIrq,
+}
+
+impl Vrc6 {
+    pub fn new(data: RomData) -> Self {
+        let is_swapped_lines = data.mapper_id == 26;
+        Self {
+            data,
+            is_swapped_lines,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0; 8],
+            banking_control: 0,
+            audio_control: 0,
+            pulses: [Vrc6Pulse::default(), Vrc6Pulse::default()],
+            sawtooth: Vrc6Sawtooth::default(),
+            irq: VrcIrq::default(),
+        }
+    }
+    //Folds the board's wiring down to $x000-$x003
+    fn register_addr(&self, addr: u16) -> u16 {
+        let reg = addr & 0xf003;
+        if self.is_swapped_lines {
+            (reg & 0xf000) | ((reg & 0x01) << 1) | ((reg & 0x02) >> 1)
+        } else {
+            reg
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / VRC6_PRG_BANK_SIZE;
+        let bank = match (addr >> 13) & 0x03 {
+            //The 16k bank is just two 8k banks in a row
+            0 | 1 => (usize::from(self.prg_bank_16k) << 1) | usize::from((addr >> 13) & 0x01),
+            2 => usize::from(self.prg_bank_8k),
+            _ => num_of_banks.saturating_sub(1),
+        };
+        let offset = usize::from(addr) & (VRC6_PRG_BANK_SIZE - 1);
+        (bank * VRC6_PRG_BANK_SIZE + offset) % self.data.p_rom.len()
+    }
+    fn chr_bank(&self, addr: u16) -> usize {
+        let slot = usize::from(addr >> 10) & 0x07;
+        //With bit 5 clear the 2k banks take A10 from the register instead of the PPU
+        let is_ppu_a10 = (self.banking_control & 0x20) == 0x20;
+        let bank_2k = |reg: u8| {
+            if is_ppu_a10 {
+                usize::from(reg & 0xfe) | (slot & 0x01)
+            } else {
+                usize::from(reg)
+            }
+        };
+        match (self.banking_control & 0x03, slot) {
+            (0, _) => usize::from(self.chr_banks[slot]),
+            (1, _) => bank_2k(self.chr_banks[slot >> 1]),
+            //Modes 2 and 3, 1k banks on the left and 2k banks on the right
+            (_, 0..=3) => usize::from(self.chr_banks[slot]),
+            (_, _) => bank_2k(self.chr_banks[4 + ((slot >> 1) & 0x01)]),
+        }
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        self.chr_bank(addr) * VRC6_CHR_BANK_SIZE + (usize::from(addr) & (VRC6_CHR_BANK_SIZE - 1))
+    }
+    fn is_sram_enable(&self) -> bool {
+        (self.banking_control & 0x80) == 0x80
+    }
+    //Bit 4 of $B003 puts CHR-ROM behind the nametables, R6 and R7 laid out like the mirroring says.
+    //The real chip has a different arrangement for each CHR mode, nothing commercial uses it so this only covers the simple case
+    fn is_chr_nametable(&self) -> bool {
+        (self.banking_control & 0x10) == 0x10 && !self.data.c_rom.is_empty()
+    }
+    fn chr_nametable_index(&self, addr: u16) -> usize {
+        let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+        usize::from(self.chr_banks[6 + page]) * VRC6_CHR_BANK_SIZE + offset
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_sram_enable() {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index]
+            } else {
+                open_bus(addr)
+            }
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_sram_enable() {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index] = data;
+            }
+        } else if !is_nondestructive {
+            let reg = self.register_addr(addr);
+            match reg {
+                0x8000..=0x8003 => self.prg_bank_16k = data & 0x0f,
+                0x9000..=0x9002 => self.pulses[0].write_reg(reg & 0x03, data),
+                0x9003 => self.audio_control = data & 0x07,
+                0xa000..=0xa002 => self.pulses[1].write_reg(reg & 0x03, data),
+                0xb000..=0xb002 => self.sawtooth.write_reg(reg & 0x03, data),
+                0xb003 => self.banking_control = data,
+                0xc000..=0xc003 => self.prg_bank_8k = data & 0x1f,
+                0xd000..=0xd003 => self.chr_banks[usize::from(reg & 0x03)] = data,
+                0xe000..=0xe003 => self.chr_banks[4 + usize::from(reg & 0x03)] = data,
+                0xf000 => self.irq.latch = data,
+                0xf001 => self.irq.write_control(data),
+                0xf002 => self.irq.acknowledge(),
+                _ => {}
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match (self.banking_control >> 2) & 0x03 {
+            0 => MirrorTable::Vertical,
+            1 => MirrorTable::Horizontal,
+            2 => MirrorTable::SingleScreenLower,
+            _ => MirrorTable::SingleScreenUpper,
+        }
+    }
+    fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        if self.is_chr_nametable() {
+            self.data.read_chr(self.chr_nametable_index(addr))
+        } else {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset]
+        }
+    }
+    fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        //Nametables in CHR-ROM can't be written
+        if !self.is_chr_nametable() {
+            let (page, offset) = convert_name_table_addr(self.mirror_table(), addr);
+            nametables[page][offset] = data;
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.irq.is_irq()
+    }
+    fn step_cpu_cycle(&mut self) {
+        self.irq.step_cpu_cycle();
+        //Halt stops all three channels where they are
+        if (self.audio_control & 0x01) == 0x01 {
+            return;
+        }
+        let shift = if (self.audio_control & 0x04) == 0x04 {
+            8
+        } else if (self.audio_control & 0x02) == 0x02 {
+            4
+        } else {
+            0
+        };
+        self.pulses[0].step_timer(shift);
+        self.pulses[1].step_timer(shift);
+        self.sawtooth.step_timer(shift);
+    }
+    fn audio_output(&self) -> f32 {
+        //The VRC6 pulses are about as loud as the APU's, so the same linear mix works for all three channels
+        let level = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        0.00752 * f32::from(level)
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.data.battery_save_data()
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.data.load_battery_save_data(data);
+    }
+}
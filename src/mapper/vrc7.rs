@@ -0,0 +1,144 @@
+/* Konami VRC7, mapper 85 */
+//http://wiki.nesdev.com/w/index.php/VRC7
+//Three switchable 8k PRG banks, eight 1k CHR banks, the VRC IRQ counter, and a 6 channel FM synthesizer (see opll.rs).
+//Lagrange Point is the only game that uses the sound, Tiny Toon Adventures 2 has the chip without it.
+//VRC7a puts the second register of each pair on A4, VRC7b uses A3. NES 2.0 submapper 1 is VRC7b and 2 is VRC7a.
+
+use super::opll::Opll;
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+use crate::rom::*;
+
+pub const VRC7_PRG_BANK_SIZE: usize = 0x2000;
+pub const VRC7_CHR_BANK_SIZE: usize = 0x0400;
+
+#[derive(Debug)]
+pub struct Vrc7 {
+    data: RomData,
+    //Which CPU address line picks the second register of a pair
+    a_mask: u16,
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    //$E000, mirroring, sound off and RAM enable
+    control: u8,
+    irq: VrcIrq,
+    opll: Opll,
+}
+
+impl Vrc7 {
+    pub fn new(data: RomData) -> Self {
+        let a_mask = match data.submapper_id {
+            1 => 0x08,
+            2 => 0x10,
+            _ => 0x18,
+        };
+        Self {
+            data,
+            a_mask,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::default(),
+            opll: Opll::default(),
+        }
+    }
+    fn prg_rom_index(&self, addr: u16) -> usize {
+        let num_of_banks = self.data.p_rom.len() / VRC7_PRG_BANK_SIZE;
+        let slot = usize::from((addr >> 13) & 0x03);
+        let bank = if slot < 3 {
+            usize::from(self.prg_banks[slot])
+        } else {
+            num_of_banks.saturating_sub(1)
+        };
+        let offset = usize::from(addr) & (VRC7_PRG_BANK_SIZE - 1);
+        (bank * VRC7_PRG_BANK_SIZE + offset) % self.data.p_rom.len()
+    }
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = usize::from(self.chr_banks[usize::from(addr >> 10) & 0x07]);
+        bank * VRC7_CHR_BANK_SIZE + (usize::from(addr) & (VRC7_CHR_BANK_SIZE - 1))
+    }
+    fn is_sram_enable(&self) -> bool {
+        (self.control & 0x80) == 0x80
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_sram_enable() {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index]
+            } else {
+                open_bus(addr)
+            }
+        } else {
+            self.data.p_rom[self.prg_rom_index(addr)]
+        }
+    }
+    fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
+            if self.is_sram_enable() {
+                let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
+                self.data.srambytes[index] = data;
+            }
+        } else if !is_nondestructive {
+            //$9010 and $9030 count as the second register even on VRC7b, that's where the sound chip is
+            let is_second = (addr & self.a_mask) != 0 || (addr & 0xf010) == 0x9010;
+            match (addr & 0xf000, is_second) {
+                (0x8000, false) => self.prg_banks[0] = data & 0x3f,
+                (0x8000, true) => self.prg_banks[1] = data & 0x3f,
+                (0x9000, false) => self.prg_banks[2] = data & 0x3f,
+                (0x9000, true) => match addr & 0x0030 {
+                    0x0010 => self.opll.select_register(data),
+                    0x0030 => self.opll.write_register(data),
+                    _ => {}
+                },
+                (0xa000, _) | (0xb000, _) | (0xc000, _) | (0xd000, _) => {
+                    let index = usize::from((addr - 0xa000) >> 12) * 2 + if is_second { 1 } else { 0 };
+                    self.chr_banks[index] = data;
+                }
+                (0xe000, false) => self.control = data,
+                (0xe000, true) => self.irq.latch = data,
+                (0xf000, false) => self.irq.write_control(data),
+                (0xf000, true) => self.irq.acknowledge(),
+                _ => {}
+            }
+        }
+    }
+    fn read_video_u8(&mut self, addr: u16) -> u8 {
+        self.data.read_chr(self.chr_index(addr))
+    }
+    fn write_video_u8(&mut self, addr: u16, data: u8) {
+        let index = self.chr_index(addr);
+        self.data.write_chr(index, data);
+    }
+    fn mirror_table(&self) -> MirrorTable {
+        match self.control & 0x03 {
+            0 => MirrorTable::Vertical,
+            1 => MirrorTable::Horizontal,
+            2 => MirrorTable::SingleScreenLower,
+            _ => MirrorTable::SingleScreenUpper,
+        }
+    }
+    fn is_irq(&self) -> bool {
+        self.irq.is_irq()
+    }
+    fn step_cpu_cycle(&mut self) {
+        self.irq.step_cpu_cycle();
+        self.opll.step_cpu_cycle();
+    }
+    fn audio_output(&self) -> f32 {
+        //Bit 6 of $E000 holds the sound chip in reset
+        if (self.control & 0x40) == 0x40 {
+            0.0
+        } else {
+            self.opll.output() * 0.5
+        }
+    }
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.data.battery_save_data()
+    }
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.data.load_battery_save_data(data);
+    }
+}
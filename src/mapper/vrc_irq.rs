@@ -0,0 +1,62 @@
+/* The IRQ counter shared by Konami's VRC4, VRC6 and VRC7 */
+//http://wiki.nesdev.com/w/index.php/VRC_IRQ
+//An 8-bit up counter that fires when it wraps past $FF and reloads from the latch.
+//In cycle mode it counts every CPU cycle, in scanline mode a prescaler divides CPU cycles by 113.667 (341/3) to fake a scanline.
+
+pub const VRC_IRQ_PRESCALER_RELOAD: i16 = 341;
+pub const VRC_IRQ_PRESCALER_STEP: i16 = 3;
+
+#[derive(Clone, Debug, Default)]
+pub struct VrcIrq {
+    pub latch: u8,
+    counter: u8,
+    prescaler: i16,
+    //Control bits, A (enable after acknowledge), E (enable), M (cycle mode)
+    is_enable_after_ack: bool,
+    is_enable: bool,
+    is_cycle_mode: bool,
+    is_irq: bool,
+}
+
+impl VrcIrq {
+    pub fn write_control(&mut self, data: u8) {
+        self.is_enable_after_ack = (data & 0x01) == 0x01;
+        self.is_enable = (data & 0x02) == 0x02;
+        self.is_cycle_mode = (data & 0x04) == 0x04;
+        //Writing the control register acknowledges, and reloads everything if it turns the counter on
+        self.is_irq = false;
+        if self.is_enable {
+            self.counter = self.latch;
+            self.prescaler = VRC_IRQ_PRESCALER_RELOAD;
+        }
+    }
+    pub fn acknowledge(&mut self) {
+        self.is_irq = false;
+        self.is_enable = self.is_enable_after_ack;
+    }
+    pub fn is_irq(&self) -> bool {
+        self.is_irq
+    }
+    pub fn step_cpu_cycle(&mut self) {
+        if !self.is_enable {
+            return;
+        }
+        if self.is_cycle_mode {
+            self.clock();
+        } else {
+            self.prescaler -= VRC_IRQ_PRESCALER_STEP;
+            if self.prescaler <= 0 {
+                self.prescaler += VRC_IRQ_PRESCALER_RELOAD;
+                self.clock();
+            }
+        }
+    }
+    fn clock(&mut self) {
+        if self.counter == 0xff {
+            self.counter = self.latch;
+            self.is_irq = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+}
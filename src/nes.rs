@@ -3,11 +3,13 @@ extern crate wasm_bindgen;
 use wasm_bindgen::prelude::*;
 pub mod system;
 pub mod rom;
+pub mod mapper;
 pub mod cpu;
 pub mod instruction;
 pub mod pad;
 pub mod ppu;
 pub mod video;
+pub mod audio;
 use crate::cpu::Cpu;
 use crate::system::System;
 
@@ -134,6 +136,26 @@ impl WasmEmulator {
             total_cycle = total_cycle + cpu_cycle;
         }
     }
+    //Audio samples made since the last clear_audio, js copies them out of wasm memory the same way as the fb
+    pub fn get_audio_ptr(&self) -> *const f32 {
+        self.cpu_sys.audio.samples.as_ptr()
+    }
+    pub fn get_audio_len(&self) -> usize {
+        self.cpu_sys.audio.samples.len()
+    }
+    pub fn get_audio_sample_rate(&self) -> u32 {
+        audio::SAMPLE_RATE
+    }
+    pub fn clear_audio(&mut self) {
+        self.cpu_sys.audio.samples.clear();
+    }
+    //Battery backed save, empty if the cartridge doesn't have one. js keeps it and hands it back after the next load
+    pub fn get_save_data(&self) -> Vec<u8> {
+        self.cpu_sys.rom.save_data().unwrap_or_default()
+    }
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.cpu_sys.rom.load_save_data(data);
+    }
     //Need to hook the buttons on the keeb up to the back end
     pub fn update_key(&mut self, key: KeyEvent) {
         match key {
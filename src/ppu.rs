@@ -4,6 +4,7 @@
 use super::cpu::*;
 use super::system::*;
 use super::video::*;
+use super::mapper::FetchPhase;
 
 pub const CPU_CYCLE_PER_LINE: usize = 341 / 3; 
 
@@ -39,6 +40,9 @@ pub const PATTERN_TABLE_ENTRY_BYTE: u16 = 16;
 
 pub const SPRITE_TEMP_SIZE: usize = 8;
 
+//With any fine x scroll the line straddles 33 tiles instead of 32
+pub const BG_TILE_FETCH_SIZE: usize = (SCREEN_TILE_WIDTH as usize) + 1;
+
 pub const NUM_OF_SPRITE: usize = 64;
 
 pub const SPRITE_SIZE: usize = 4;
@@ -154,6 +158,16 @@ impl Sprite {
         }
     }
 }
+//One background tile worth of fetched data, the palette out of the attribute table and the two pattern bytes
+#[derive(Copy, Clone, Default)]
+pub struct BgTile {
+
+    palette_id: u8,
+
+    data_lower: u8,
+
+    data_upper: u8,
+}
 //The PPU has 4 ways of looking at lines, as it scans down the screen. This amounts to 4 rendering phases at the hardware level
 //The line names are fairly self-explanatory except vblank, which is the period an old TV took to scan back to the top, blank the screen, and
 //begin drawing again
@@ -189,6 +203,9 @@ pub struct Ppu {
     //This is another OAM that holds a max of 8 sprites for the current scanline
     //This means there are limits to the amount of sprites you can have along a single line
     pub sprite_temps: [Option<Sprite>; SPRITE_TEMP_SIZE],
+    //What got fetched for the current line, the drawing works off of these
+    pub bg_tiles: [BgTile; BG_TILE_FETCH_SIZE],
+    pub sprite_patterns: [(u8, u8); SPRITE_TEMP_SIZE],
 
     //Basically the PPUs way of syncing
     pub cumulative_cpu_cyc: usize,
@@ -214,6 +231,8 @@ impl Default for Ppu {
         Self {
             oam: [0; OAM_SIZE],
             sprite_temps: [None; SPRITE_TEMP_SIZE],
+            bg_tiles: [BgTile::default(); BG_TILE_FETCH_SIZE],
+            sprite_patterns: [(0, 0); SPRITE_TEMP_SIZE],
 
             cumulative_cpu_cyc: 0,
             current_line: 241,
@@ -234,6 +253,8 @@ impl Ppu {
    pub fn reset(&mut self) {
         self.oam = [0; OAM_SIZE];
         self.sprite_temps = [None; SPRITE_TEMP_SIZE];
+        self.bg_tiles = [BgTile::default(); BG_TILE_FETCH_SIZE];
+        self.sprite_patterns = [(0, 0); SPRITE_TEMP_SIZE];
 
         self.current_line = 241;
         self.cumulative_cpu_cyc = 0;
@@ -285,29 +306,17 @@ impl Ppu {
         //Return to pre-transfer
         self.is_dma_running = is_pre_transfer;
     }
-    //Put a line on the fb (frame buffer). Fun fact: this frame buffer is directly used way up in the browser to draw on the canvas
-    fn draw_line(
-        &mut self,
-        system: &mut System,
-        fb: &mut [[[u8; NUM_OF_COLOR]; VISIBLE_SCREEN_WIDTH]; VISIBLE_SCREEN_HEIGHT],
-    ) {
+    //Fetch the background tiles for this line. The real PPU goes tile by tile, reading the nametable, then the attribute table,
+    //then the two pattern bytes, and cartridges can see (and react to) every one of those reads, so we do them in that order too
+    fn fetch_bg_line(&mut self, system: &mut System) {
+        let is_large_sprite = system.read_ppu_sprite_height() == 16;
+        system.rom.notify_fetch_phase(FetchPhase::Background { is_large_sprite });
         //This is where the very clever part (read: difficult) part of the PPU starts
         //https://wiki.nesdev.com/w/index.php/PPU_nametables
         //The PPU nametable is specifically used to lay out backgrounds
         let nametable_base_addr = system.read_ppu_name_table_base_addr();
         //The pattern table defines background and sprite shapes.
         let pattern_table_addr = system.read_ppu_bg_pattern_table_addr();
-        //Are we clipping off the screen
-        let is_clip_bg_leftend = system.read_ppu_is_clip_bg_leftend();
-        //Are we writing to the background
-        let is_write_bg = system.read_ppu_is_write_bg();
-        //self-explanatory
-        let is_monochrome = system.read_is_monochrome();
-        //We find the "master color" for the palette
-        let master_bg_color = Color::from(system.video.read_u8(
-            &mut system.rom,
-            PALETTE_TABLE_BASE_ADDR + PALETTE_BG_OFFSET,
-        ));
         //Fairly standard x/y math coordinate math, but you know, old
         let raw_y = self.current_line + u16::from(self.current_scroll_y);
         let offset_y = raw_y & 0x07; 
@@ -318,17 +327,10 @@ impl Ppu {
                                                                
         let is_nametable_position_top = tile_global_y < SCREEN_TILE_HEIGHT;
 
-       
-        let pixel_y = usize::from(self.current_line);
-        //We need to go across for every scanline
-        for pixel_x in 0..VISIBLE_SCREEN_WIDTH {
-            
-            let (sprite_palette_data_back, sprite_palette_data_front) =
-                self.get_sprite_draw_data(system, pixel_x, pixel_y);
-
+        //We need to go across for every tile touched by the scanline
+        for tile_index in 0..BG_TILE_FETCH_SIZE {
             //Same as above but going horizontally
-            let offset_x = ((pixel_x as u16) + u16::from(self.current_scroll_x)) & 0x07;
-            let tile_base_x = ((pixel_x as u16) + u16::from(self.current_scroll_x)) >> 3;
+            let tile_base_x = (u16::from(self.current_scroll_x) >> 3) + (tile_index as u16);
            
             let tile_global_x = tile_base_x % (SCREEN_TILE_WIDTH * 2);
             let tile_local_x = tile_global_x % SCREEN_TILE_WIDTH;
@@ -338,6 +340,12 @@ impl Ppu {
             let target_nametable_base_addr = nametable_base_addr +
                 (if is_nametable_position_left { 0x0000 } else { 0x0400 }) + 
                 (if is_nametable_position_top  { 0x0000 } else { 0x0800 }); 
+
+            //Nametable as mentioned above, this is how we map into it given a tile
+            let nametable_addr = target_nametable_base_addr + (tile_local_y << 5) + tile_local_x;
+            //We get the background tile from the nametable address, as mentioned, the nametable defines the background
+            let bg_tile_id = u16::from(system.video.read_u8(&mut system.rom, nametable_addr));
+
             //https://wiki.nesdev.com/w/index.php/PPU_attribute_tables
             //https://wiki.nesdev.com/w/index.php/PPU_scrolling#Tile_and_attribute_fetching
             //The attribute table is a 64-byte array at the end of each nametable that controls which palette is assigned to each part of the background. 
@@ -347,36 +355,137 @@ impl Ppu {
             let attribute_addr =
                 attribute_base_addr + (attribute_y_offset << 3) + attribute_x_offset;
 
-            
             let raw_attribute = system.video.read_u8(&mut system.rom, attribute_addr);
-            let bg_palette_id = match (tile_local_x & 0x03 < 0x2, tile_local_y & 0x03 < 0x2) {
+            let palette_id = match (tile_local_x & 0x03 < 0x2, tile_local_y & 0x03 < 0x2) {
                 (true, true) => (raw_attribute >> 0) & 0x03,  // top left
                 (false, true) => (raw_attribute >> 2) & 0x03, // top right
                 (true, false) => (raw_attribute >> 4) & 0x03, // bottom left
                 (false, false) => (raw_attribute >> 6) & 0x03, // bottom right
             };
 
-            //Nametable as mentioned above, this is how we map into it given a tile
-            let nametable_addr = target_nametable_base_addr + (tile_local_y << 5) + tile_local_x;
-            //We get the background tile from the nametable address, as mentioned, the nametable defines the background
-            let bg_tile_id = u16::from(system.video.read_u8(&mut system.rom, nametable_addr));
             //The background is built from the lower and upper bytes of the background pattern table
             //https://wiki.nesdev.com/w/index.php/PPU_pattern_tables
             let bg_pattern_table_base_addr = pattern_table_addr + (bg_tile_id << 4);
             let bg_pattern_table_addr_lower = bg_pattern_table_base_addr + offset_y;
             let bg_pattern_table_addr_upper = bg_pattern_table_addr_lower + 8;
             //We grab the background data from video memory with this calculated pattern table address
-            let bg_data_lower = system
+            let data_lower = system
                 .video
                 .read_u8(&mut system.rom, bg_pattern_table_addr_lower);
-            let bg_data_upper = system
+            let data_upper = system
                 .video
                 .read_u8(&mut system.rom, bg_pattern_table_addr_upper);
+
+            self.bg_tiles[tile_index] = BgTile {
+                palette_id,
+                data_lower,
+                data_upper,
+            };
+        }
+    }
+    //Fetch the pattern bytes for the sprites picked by fetch_sprite. This happens after the background on real hardware,
+    //and all 8 slots get fetched even when they're empty (tile $FF stands in), which is what MMC3 style scanline counters rely on
+    fn fetch_sprite_line(&mut self, system: &mut System) {
+        let pixel_y = usize::from(self.current_line);
+        let is_large = system.read_ppu_sprite_height() == 16;
+        system.rom.notify_fetch_phase(FetchPhase::Sprite { is_large_sprite: is_large });
+        for slot in 0..SPRITE_TEMP_SIZE {
+            let sprite = if system.read_ppu_is_write_sprite() {
+                self.sprite_temps[slot]
+            } else {
+                None
+            };
+            let sprite_pattern_table_addr_lower = if let Some(sprite) = sprite {
+                //Figure out where the sprite is on the screen
+                let sprite_y = usize::from(sprite.y);
+                let sprite_offset_y: usize = pixel_y - sprite_y - 1; 
+                debug_assert!(sprite_offset_y < usize::from(system.read_ppu_sprite_height()));
+                //Draw it based in attributes and the pattern table
+                let (sprite_pattern_table_addr, sprite_tile_id): (u16, u8) = match sprite
+                    .tile_id
+                {
+                    TileId::Normal { id } => (system.read_ppu_sprite_pattern_table_addr(), id),
+                   
+                    TileId::Large {
+                        pattern_table_addr,
+                        upper_tile_id,
+                        lower_tile_id,
+                    } => {
+                        let is_upper = sprite_offset_y < SPRITE_NORMAL_HEIGHT;
+                        let is_vflip = sprite.attr.is_vert_flip; 
+                        let id = match (is_upper, is_vflip) {
+                            (true, false) => upper_tile_id,  
+                            (false, false) => lower_tile_id, 
+                            (true, true) => lower_tile_id,   
+                            (false, true) => upper_tile_id,  
+                        };
+                        (pattern_table_addr, id)
+                    }
+                };
+                //Do the math for the vertical flip, horizontal gets done when we draw
+                let tile_offset_y: usize = if !sprite.attr.is_vert_flip {
+                    sprite_offset_y % SPRITE_NORMAL_HEIGHT
+                } else {
+                    SPRITE_NORMAL_HEIGHT - 1 - (sprite_offset_y % SPRITE_NORMAL_HEIGHT)
+                };
+                //Get the sprite out of the pattern table, similar to how we treated tiles up above
+                let sprite_pattern_table_base_addr = sprite_pattern_table_addr
+                    + (u16::from(sprite_tile_id) * PATTERN_TABLE_ENTRY_BYTE);
+                sprite_pattern_table_base_addr + (tile_offset_y as u16)
+            } else if is_large {
+                //Empty slot, tile $FF of an 8x16 sprite lands in the $1000 table
+                0x1000 + (0xfe * PATTERN_TABLE_ENTRY_BYTE)
+            } else {
+                system.read_ppu_sprite_pattern_table_addr() + (0xff * PATTERN_TABLE_ENTRY_BYTE)
+            };
+            let sprite_pattern_table_addr_upper = sprite_pattern_table_addr_lower + 8;
+            let sprite_data_lower = system
+                .video
+                .read_u8(&mut system.rom, sprite_pattern_table_addr_lower);
+            let sprite_data_upper = system
+                .video
+                .read_u8(&mut system.rom, sprite_pattern_table_addr_upper);
+            self.sprite_patterns[slot] = (sprite_data_lower, sprite_data_upper);
+        }
+        //Done with the line, anything else until the next one comes from the CPU
+        system.rom.notify_fetch_phase(FetchPhase::Idle);
+    }
+    //Put a line on the fb (frame buffer). Fun fact: this frame buffer is directly used way up in the browser to draw on the canvas
+    fn draw_line(
+        &mut self,
+        system: &mut System,
+        fb: &mut [[[u8; NUM_OF_COLOR]; VISIBLE_SCREEN_WIDTH]; VISIBLE_SCREEN_HEIGHT],
+    ) {
+        //Are we clipping off the screen
+        let is_clip_bg_leftend = system.read_ppu_is_clip_bg_leftend();
+        //Are we writing to the background
+        let is_write_bg = system.read_ppu_is_write_bg();
+        //self-explanatory
+        let is_monochrome = system.read_is_monochrome();
+        //We find the "master color" for the palette
+        let master_bg_color = Color::from(system.video.read_u8(
+            &mut system.rom,
+            PALETTE_TABLE_BASE_ADDR + PALETTE_BG_OFFSET,
+        ));
+       
+        let pixel_y = usize::from(self.current_line);
+        //We need to go across for every scanline
+        for pixel_x in 0..VISIBLE_SCREEN_WIDTH {
+            
+            let (sprite_palette_data_back, sprite_palette_data_front) =
+                self.get_sprite_draw_data(system, pixel_x);
+
+            //Which of the fetched tiles this pixel lands in
+            let scrolled_x = (pixel_x as u16) + u16::from(self.current_scroll_x);
+            let offset_x = scrolled_x & 0x07;
+            let tile_index = usize::from((scrolled_x >> 3) - (u16::from(self.current_scroll_x) >> 3));
+            let bg_tile = self.bg_tiles[tile_index];
+
             //We take the background data and map it onto the palette
-            let bg_palette_offset = (((bg_data_upper >> (7 - offset_x)) & 0x01) << 1)
-                | ((bg_data_lower >> (7 - offset_x)) & 0x01);
+            let bg_palette_offset = (((bg_tile.data_upper >> (7 - offset_x)) & 0x01) << 1)
+                | ((bg_tile.data_lower >> (7 - offset_x)) & 0x01);
             let bg_palette_addr = (PALETTE_TABLE_BASE_ADDR + PALETTE_BG_OFFSET) +   
-                (u16::from(bg_palette_id) << 2) + 
+                (u16::from(bg_tile.palette_id) << 2) + 
                 u16::from(bg_palette_offset);
 
             //We check if something is clipping off the left side of the screen
@@ -429,7 +538,6 @@ impl Ppu {
         &mut self,
         system: &mut System,
         pixel_x: usize,
-        pixel_y: usize,
     ) -> (Option<u8>, Option<u8>) {
         //If the ppu isn't doing anything with sprites, we don't need to do anything
         if !system.read_ppu_is_write_sprite() {
@@ -440,11 +548,12 @@ impl Ppu {
         let mut sprite_palette_data_front: Option<u8> = None; 
         
         //This moves across the scanline sprite template thingy to get the sprites in the scanline 
-        'draw_sprite: for &s in self.sprite_temps.iter() {
+        'draw_sprite: for (&s, &(sprite_data_lower, sprite_data_upper)) in
+            self.sprite_temps.iter().zip(self.sprite_patterns.iter())
+        {
             if let Some(sprite) = s {
                 //If we get a sprite, we can do stuff
                 let sprite_x = usize::from(sprite.x);
-                let sprite_y = usize::from(sprite.y);
                 //Like check for clipping pixels (with the left, (i.e the void))
                 let is_sprite_clipping = system.read_ppu_is_clip_sprite_leftend() && (pixel_x < 8);
                 //If it's not clipping and we are currently inside it
@@ -452,56 +561,14 @@ impl Ppu {
                     && (sprite_x <= pixel_x)
                     && (pixel_x < usize::from(sprite_x + SPRITE_WIDTH))
                 {
-                    //Figure out where the sprite is on the screen
                     let sprite_offset_x: usize = pixel_x - sprite_x; 
-                    let sprite_offset_y: usize = pixel_y - sprite_y - 1; 
                     debug_assert!(sprite_offset_x < SPRITE_WIDTH);
-                    debug_assert!(sprite_offset_y < usize::from(system.read_ppu_sprite_height()));
-                    //Draw it based in attributes and the pattern table
-                    let (sprite_pattern_table_addr, sprite_tile_id): (u16, u8) = match sprite
-                        .tile_id
-                    {
-                        TileId::Normal { id } => (system.read_ppu_sprite_pattern_table_addr(), id),
-                       
-                        TileId::Large {
-                            pattern_table_addr,
-                            upper_tile_id,
-                            lower_tile_id,
-                        } => {
-                            let is_upper = sprite_offset_y < SPRITE_NORMAL_HEIGHT;
-                            let is_vflip = sprite.attr.is_vert_flip; 
-                            let id = match (is_upper, is_vflip) {
-                                (true, false) => upper_tile_id,  
-                                (false, false) => lower_tile_id, 
-                                (true, true) => lower_tile_id,   
-                                (false, true) => upper_tile_id,  
-                            };
-                            (pattern_table_addr, id)
-                        }
-                    };
-                    //Do the math for the flippings
+                    //Do the math for the flippings, the pattern bytes were already fetched the right way up
                     let tile_offset_x: usize = if !sprite.attr.is_hor_flip {
                         sprite_offset_x
                     } else {
                         SPRITE_WIDTH - 1 - sprite_offset_x
                     };
-                    let tile_offset_y: usize = if !sprite.attr.is_vert_flip {
-                        sprite_offset_y % SPRITE_NORMAL_HEIGHT
-                    } else {
-                        SPRITE_NORMAL_HEIGHT - 1 - (sprite_offset_y % SPRITE_NORMAL_HEIGHT)
-                    };
-                    //Get the sprite out of the pattern table, similar to how we treated tiles up above
-                    let sprite_pattern_table_base_addr = u16::from(sprite_pattern_table_addr)
-                        + (u16::from(sprite_tile_id) * PATTERN_TABLE_ENTRY_BYTE);
-                    let sprite_pattern_table_addr_lower =
-                        sprite_pattern_table_base_addr + (tile_offset_y as u16);
-                    let sprite_pattern_table_addr_upper = sprite_pattern_table_addr_lower + 8;
-                    let sprite_data_lower = system
-                        .video
-                        .read_u8(&mut system.rom, sprite_pattern_table_addr_lower);
-                    let sprite_data_upper = system
-                        .video
-                        .read_u8(&mut system.rom, sprite_pattern_table_addr_upper);
                    
                     let sprite_palette_offset =
                         (((sprite_data_upper >> (7 - tile_offset_x)) & 0x01) << 1)
@@ -606,12 +673,21 @@ impl Ppu {
         
         system.write_ppu_is_hit_sprite0(false);
         system.write_ppu_is_sprite_overflow(false);
+        //Let the cartridge know we're on a new line, some boards count these
+        system.rom.step_scanline(self.current_line);
 
         //Get the line status, act accordingly
         match LineStatus::from(self.current_line) {
             LineStatus::Visible => {
               
                 self.fetch_sprite(system);
+                //The PPU only touches memory while it's rendering something
+                if system.read_ppu_is_write_bg() || system.read_ppu_is_write_sprite() {
+                    self.fetch_bg_line(system);
+                    self.fetch_sprite_line(system);
+                } else {
+                    self.bg_tiles = [BgTile::default(); BG_TILE_FETCH_SIZE];
+                }
               
                 self.draw_line(system, fb);
                 
@@ -636,6 +712,13 @@ impl Ppu {
                 }
             }
             LineStatus::PreRender => {
+                //Nothing gets drawn, but the pre-render line still does all the same fetches
+                //(with no sprites picked), and the cartridge can see them
+                if system.read_ppu_is_write_bg() || system.read_ppu_is_write_sprite() {
+                    self.sprite_temps = [None; SPRITE_TEMP_SIZE];
+                    self.fetch_bg_line(system);
+                    self.fetch_sprite_line(system);
+                }
                 self.current_line = (self.current_line + 1) % RENDER_SCREEN_HEIGHT;
                
                 system.write_ppu_is_vblank(false);
@@ -1,15 +1,28 @@
 /* Binary loading and handling */
 use wasm_bindgen::prelude::*;
+use super::mapper;
+use super::mapper::{FetchPhase, Mapper};
+use super::video::NameTables;
+use super::mapper::nrom::Nrom;
 
 
-pub const PRG_ROM_MAX_SIZE: usize = 0x8000;
-pub const CHR_ROM_MAX_SIZE: usize = 0x2000;
+pub const PRG_ROM_BANK_SIZE: usize = 0x4000;
+pub const CHR_ROM_BANK_SIZE: usize = 0x2000;
+//Size of the $6000-$7FFF window, boards with more than this bank switch it
 pub const BATTERY_PACKED_RAM_MAX_SIZE: usize = 0x2000;
 
 pub const PRG_ROM_SYSTEM_BASE_ADDR: u16 = 0x8000;
 pub const BATTERY_PACKED_RAM_BASE_ADDR: u16 = 0x6000;
 
+//Nothing drives the data bus, what's left floating there is usually the upper address byte
+pub fn open_bus(addr: u16) -> u8 {
+    (addr >> 8) as u8
+}
+
+pub const INES_HEADER_SIZE: usize = 0x0010;
 pub const INES_TRAINER_DATA_SIZE: usize = 0x0200;
+//The trainer gets loaded into battery RAM at $7000
+pub const INES_TRAINER_SRAM_OFFSET: usize = 0x1000;
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
@@ -46,11 +59,6 @@ macro_rules! arr_write {
         $arr[$index] = $data
     };
 }
-#[derive(Debug, Clone)]
-pub enum Mapper{
-    Unknown,
-    Nrom,
-}
 //Defines the nametable mirroring pattern.
 //http://wiki.nesdev.com/w/index.php/Mirroring#Nametable_Mirroring
 #[derive(Copy, Clone, Debug)]
@@ -58,43 +66,114 @@ pub enum MirrorTable{
     Unknown,
     Horizontal,
     Vertical,
-    SingleScreen,
+    //Single screen can be either of the two nametables, and the boards that use it switch between them at runtime
+    SingleScreenLower,
+    SingleScreenUpper,
     FourScreen,
 }
 
-//This is the "game cartdridge" structure, in INES format
+//Everything pulled out of the INES file. This gets handed over to whichever mapper the header asks for,
+//and the mapper owns it from then on
 //http://wiki.nesdev.com/w/index.php/INES
 #[derive(Clone, Debug)]
-pub struct Rom{
-    //Which mapper we have defined, this emulator only supports mapper 0 (the most basic of games like original Super Mario)
-    pub mapper: Mapper,
-    //The mirror table, see the mirror table enum above.
+pub struct RomData{
+    //Mapper number, 0-255 for INES, up to 4095 for NES 2.0
+    pub mapper_id: u16,
+    //NES 2.0 submapper, 0 when the header doesn't say
+    pub submapper_id: u8,
+    //The mirror table the board is soldered for, mappers with mirroring control ignore this
     pub mirror_table: MirrorTable,
-    //The SRAM
+    //Header's "four screen" bit. Most boards that set it really do have 4k of nametable RAM, but a few use it to pick a board variant
+    pub is_alt_nametables: bool,
+    //Is the SRAM battery backed
     pub sram : bool,
-    //Program  memory size
-    pub p_rom_bytes : usize,
-    //Character memory size, these are the graphics
-    pub c_rom_bytes : usize,
     //Actual program on the rom
-    pub p_rom:[u8; PRG_ROM_MAX_SIZE],
+    pub p_rom: Vec<u8>,
     //Actual graphics
-    pub c_rom:[u8; CHR_ROM_MAX_SIZE],
+    pub c_rom: Vec<u8>,
+    //Boards with no graphics on the ROM have RAM here instead, and the game uploads its tiles itself
+    pub c_ram: Vec<u8>,
     //The ram we can modify on the ROM (I know, I know)
-    pub srambytes:[u8;BATTERY_PACKED_RAM_MAX_SIZE],
+    pub srambytes: Vec<u8>,
+}
+
+impl RomData {
+    //Pattern table access goes to whichever chip the board has, ROM if it has any
+    pub fn read_chr(&self, index: usize) -> u8 {
+        if self.c_rom.is_empty() {
+            self.c_ram[index % self.c_ram.len()]
+        } else {
+            self.c_rom[index % self.c_rom.len()]
+        }
+    }
+    //Writes only stick when it's RAM
+    pub fn write_chr(&mut self, index: usize, data: u8) {
+        if self.c_rom.is_empty() {
+            let len = self.c_ram.len();
+            self.c_ram[index % len] = data;
+        }
+    }
+    //For boards with both chips, straight to the RAM whether there's ROM or not
+    pub fn read_chr_ram(&self, index: usize) -> u8 {
+        self.c_ram[index % self.c_ram.len()]
+    }
+    pub fn write_chr_ram(&mut self, index: usize, data: u8) {
+        let len = self.c_ram.len();
+        self.c_ram[index % len] = data;
+    }
+    pub fn is_chr_ram(&self) -> bool {
+        self.c_rom.is_empty()
+    }
+    //The layout the header asks for. The four screen bit means the board carries another 2k of nametable RAM, and wins over H/V
+    pub fn header_mirror_table(&self) -> MirrorTable {
+        if self.is_alt_nametables {
+            MirrorTable::FourScreen
+        } else {
+            self.mirror_table
+        }
+    }
+    //Battery backed PRG RAM for the save path, only when the header says there's a battery
+    pub fn battery_save_data(&self) -> Option<Vec<u8>> {
+        if self.sram {
+            Some(self.srambytes.clone())
+        } else {
+            None
+        }
+    }
+    pub fn load_battery_save_data(&mut self, data: &[u8]) {
+        let len = self.srambytes.len().min(data.len());
+        self.srambytes[..len].copy_from_slice(&data[..len]);
+    }
+}
+
+impl Default for RomData {
+    //Blank NROM sized cartridge, this is what sits in the slot before anything gets loaded
+    fn default() -> Self {
+        Self{
+            mapper_id: 0,
+            submapper_id: 0,
+            mirror_table: MirrorTable::Unknown,
+            is_alt_nametables: false,
+            sram: false,
+            p_rom: vec![0; PRG_ROM_BANK_SIZE * 2],
+            c_rom: vec![],
+            c_ram: vec![0; CHR_ROM_BANK_SIZE],
+            srambytes: vec![0; BATTERY_PACKED_RAM_MAX_SIZE],
+        }
+    }
+}
+
+//This is the "game cartdridge" structure. The board logic itself lives in the mapper modules,
+//this just parses the INES file and forwards bus accesses to whatever got plugged in
+#[derive(Debug)]
+pub struct Rom{
+    pub mapper: Box<dyn Mapper>,
 }
 
 impl Rom{
     pub fn default() -> Self {
         Self{
-            mapper : Mapper::Unknown,
-            mirror_table: MirrorTable::Unknown,
-            sram : false,
-            p_rom_bytes: 0,
-            c_rom_bytes : 0,
-            p_rom: [0; PRG_ROM_MAX_SIZE],
-            c_rom: [0; CHR_ROM_MAX_SIZE],
-            srambytes: [0; BATTERY_PACKED_RAM_MAX_SIZE],
+            mapper: Box::new(Nrom::new(RomData::default())),
         }
     }
     pub fn load_bin(&mut self, read_f:impl Fn(usize) -> u8) -> bool{
@@ -117,118 +196,144 @@ impl Rom{
         let p_rom_sz = usize::from(read_f(4));
         //Same here except its 8k increments
         let c_rom_sz = usize::from(read_f(5));
-        //Mapper flag 1, we only support 1 mapper and rom type so this is the only flag we actually use
         let flags6  = read_f(6); // Mapper 1
-        let _flags7  = read_f(7); // Mapper 2
-        let _flags8  = read_f(8); // Ram Size
+        let flags7  = read_f(7); // Mapper 2
+        let flags8  = read_f(8); // Ram Size, or mapper/submapper in NES 2.0
         let _flags9  = read_f(9); // tv system 1
-        let _flags10 = read_f(10); // tv system 2
-        debug_assert!(p_rom_sz > 0);
+        let flags10 = read_f(10); // tv system 2, or PRG RAM size in NES 2.0
+        let flags11 = read_f(11); // CHR RAM size in NES 2.0
+        if p_rom_sz == 0 {
+            return false;
+        }
         //Are we mirroring vertically?
         let is_vert_m = (flags6 & 0x01) == 0x01;
-        if is_vert_m{
-            self.mirror_table = MirrorTable::Vertical;
+        let mirror_table = if is_vert_m{
+            MirrorTable::Vertical
         }else{
-            self.mirror_table = MirrorTable::Horizontal;
-        }
-        self.sram = (flags6 & 0x02) == 0x02;
+            MirrorTable::Horizontal
+        };
+        let is_alt_nametables = (flags6 & 0x08) == 0x08;
+        let sram = (flags6 & 0x02) == 0x02;
         let trainer_exists = (flags6 & 0x04) == 0x04;
-        let header_bytes = 16;
-        //I am not sure what a trainer is in this context. But its in the INES spec so we have to account for the bytes
-        let trainer_bytes = if trainer_exists { 512 } else { 0 };
-        let prg_rom_bytes = p_rom_sz * 0x4000; 
-        let chr_rom_bytes = c_rom_sz * 0x2000; 
-        let trainer_baseaddr = header_bytes;
-        let prg_rom_baseaddr = header_bytes + trainer_bytes;
-        let chr_rom_baseaddr = header_bytes + trainer_bytes + prg_rom_bytes;
-
-        self.mapper = Mapper::Nrom;
-        debug_assert!(prg_rom_bytes <= PRG_ROM_MAX_SIZE);
-        debug_assert!(chr_rom_bytes <= CHR_ROM_MAX_SIZE);
+        //The mapper number is split across the upper nibbles of flags 6 and 7
+        //http://wiki.nesdev.com/w/index.php/NES_2.0 adds another nibble and a submapper in flags 8
+        let is_nes2 = (flags7 & 0x0c) == 0x08;
+        let mut mapper_id = u16::from(flags6 >> 4) | u16::from(flags7 & 0xf0);
+        let mut submapper_id = 0;
+        //Plain INES gives the PRG RAM size in 8k units in flags 8, where 0 still means 8k
+        let mut sram_bytes = usize::from(flags8.max(1)) * BATTERY_PACKED_RAM_MAX_SIZE;
+        //No CHR ROM means the board has 8k of CHR RAM instead
+        let mut chr_ram_bytes = if c_rom_sz == 0 { CHR_ROM_BANK_SIZE } else { 0 };
+        if is_nes2 {
+            mapper_id |= u16::from(flags8 & 0x0f) << 8;
+            submapper_id = flags8 >> 4;
+            //NES 2.0 gives volatile and battery backed RAM as shift counts, 64 << n bytes each
+            let shift_to_bytes = |shift: u8| if shift == 0 { 0 } else { 64usize << shift };
+            sram_bytes = shift_to_bytes(flags10 & 0x0f) + shift_to_bytes(flags10 >> 4);
+            chr_ram_bytes = shift_to_bytes(flags11 & 0x0f) + shift_to_bytes(flags11 >> 4);
+            if c_rom_sz == 0 {
+                chr_ram_bytes = chr_ram_bytes.max(CHR_ROM_BANK_SIZE);
+            }
+        }
+        //The trainer is 512 bytes of code some copiers wanted at $7000, we just put it there
+        let trainer_bytes = if trainer_exists { INES_TRAINER_DATA_SIZE } else { 0 };
+        let prg_rom_bytes = p_rom_sz * PRG_ROM_BANK_SIZE;
+        let chr_rom_bytes = c_rom_sz * CHR_ROM_BANK_SIZE;
+        let trainer_baseaddr = INES_HEADER_SIZE;
+        let prg_rom_baseaddr = INES_HEADER_SIZE + trainer_bytes;
+        let chr_rom_baseaddr = INES_HEADER_SIZE + trainer_bytes + prg_rom_bytes;
+
+        let mut data = RomData{
+            mapper_id,
+            submapper_id,
+            mirror_table,
+            is_alt_nametables,
+            sram,
+            p_rom: vec![0; prg_rom_bytes],
+            c_rom: vec![0; chr_rom_bytes],
+            c_ram: vec![0; chr_ram_bytes],
+            //Always have at least the 8k at $6000-$7FFF, boards without RAM just never get it written
+            srambytes: vec![0; sram_bytes.max(BATTERY_PACKED_RAM_MAX_SIZE)],
+        };
         //Load everything in
         if trainer_exists {
             for i in 0..INES_TRAINER_DATA_SIZE {
                 let ines_binary_addr = trainer_baseaddr + i;
-                self.p_rom[i] = read_f(ines_binary_addr);
+                data.srambytes[INES_TRAINER_SRAM_OFFSET + i] = read_f(ines_binary_addr);
             }
         }
         for i in 0..prg_rom_bytes {
             let bin_addr = prg_rom_baseaddr + i;
-            self.p_rom[i] = read_f(bin_addr);
-           
+            data.p_rom[i] = read_f(bin_addr);
         }
-            
         for i in 0..chr_rom_bytes{
             let bin_addr = chr_rom_baseaddr + i;
-            self.c_rom[i] = read_f(bin_addr);
-            
+            data.c_rom[i] = read_f(bin_addr);
         }
 
-        self.p_rom_bytes= prg_rom_bytes;
-        self.c_rom_bytes = chr_rom_bytes;
-
-        true
-    }
-    //Read 8 bytes from ROM, mapped out appropriately
-   pub fn read_u8(&mut self, addr: u16, _is_nondestructive: bool) -> u8 {
-        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
-            debug_assert!(addr >= BATTERY_PACKED_RAM_BASE_ADDR);
-
-            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
-            arr_read!(self.srambytes, index)
-        } else {
-            debug_assert!(addr >= PRG_ROM_SYSTEM_BASE_ADDR);
-
-            let index = usize::from(addr - PRG_ROM_SYSTEM_BASE_ADDR);
-        
-            if index < self.p_rom_bytes {
-                arr_read!(self.p_rom, index)
-            } else {
-                arr_read!(self.p_rom, index - self.p_rom_bytes)
+        //Plug in whichever board the header asks for, anything we don't know about gets refused
+        match mapper::from_rom_data(data) {
+            Some(mapper) => {
+                self.mapper = mapper;
+                true
+            }
+            None => {
+                log(&format!("unsupported mapper {}", mapper_id));
+                false
             }
         }
     }
+    //Read 8 bytes from the cartridge, the mapper decides where it actually comes from
+    //$4020-$5FFF is open bus unless the board has something down there
+    pub fn read_u8(&mut self, addr: u16, is_nondestructive: bool) -> u8 {
+        if addr < BATTERY_PACKED_RAM_BASE_ADDR && !self.mapper.is_expansion_area() {
+            return open_bus(addr);
+        }
+        self.mapper.read_u8(addr, is_nondestructive)
+    }
     //Same as above for write
-    pub fn write_u8(&mut self, addr: u16, data: u8, _is_nondestructive: bool) {
-        if addr < PRG_ROM_SYSTEM_BASE_ADDR {
-            debug_assert!(addr >= BATTERY_PACKED_RAM_BASE_ADDR);
-
-            let index = usize::from(addr - BATTERY_PACKED_RAM_BASE_ADDR);
-            arr_write!(self.srambytes, index, data)
-        } else {
-            debug_assert!(addr >= PRG_ROM_SYSTEM_BASE_ADDR);
-
-            let index = usize::from(addr - PRG_ROM_SYSTEM_BASE_ADDR);
-
-            if index < self.p_rom_bytes {
-                arr_write!(self.p_rom, index, data);
-            } else {
-                arr_write!(self.p_rom, index - self.p_rom_bytes, data);
-            }
+    pub fn write_u8(&mut self, addr: u16, data: u8, is_nondestructive: bool) {
+        if addr < BATTERY_PACKED_RAM_BASE_ADDR && !self.mapper.is_expansion_area() {
+            return;
         }
+        self.mapper.write_u8(addr, data, is_nondestructive)
     }
     //Reads and writes to graphics memory
     pub fn read_video_u8(&mut self, addr: u16) -> u8 {
-            let index = usize::from(addr);
-            debug_assert!(index < CHR_ROM_MAX_SIZE);
-            arr_read!(self.c_rom, index)
-        }
-       
+        self.mapper.read_video_u8(addr)
+    }
     pub fn write_video_u8(&mut self, addr: u16, data: u8) {
-            let index = usize::from(addr);
-            debug_assert!(index < CHR_ROM_MAX_SIZE);
-            arr_write!(self.c_rom, index, data);
-        }
-      
+        self.mapper.write_video_u8(addr, data)
+    }
+    pub fn read_nametable_u8(&mut self, nametables: &NameTables, addr: u16) -> u8 {
+        self.mapper.read_nametable_u8(nametables, addr)
+    }
+    pub fn write_nametable_u8(&mut self, nametables: &mut NameTables, addr: u16, data: u8) {
+        self.mapper.write_nametable_u8(nametables, addr, data)
+    }
+    pub fn notify_fetch_phase(&mut self, phase: FetchPhase) {
+        self.mapper.notify_fetch_phase(phase)
+    }
+    pub fn is_irq(&self) -> bool {
+        self.mapper.is_irq()
+    }
+    pub fn step_cpu_cycle(&mut self) {
+        self.mapper.step_cpu_cycle()
+    }
+    pub fn step_scanline(&mut self, line: u16) {
+        self.mapper.step_scanline(line)
+    }
+    pub fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+    pub fn save_data(&self) -> Option<Vec<u8>> {
+        self.mapper.save_data()
+    }
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.mapper.load_save_data(data)
+    }
     pub fn reset(&mut self) {
-            self.mapper = Mapper::Unknown;
-            self.mirror_table = MirrorTable::Unknown;
-            self.sram = false;
-            self.p_rom_bytes = 0;
-            self.c_rom_bytes = 0;
-            self.p_rom = [0; PRG_ROM_MAX_SIZE];
-            self.c_rom = [0; CHR_ROM_MAX_SIZE];
-            self.srambytes = [0; BATTERY_PACKED_RAM_MAX_SIZE];
-        }
+        self.mapper = Box::new(Nrom::new(RomData::default()));
+    }
 
 }
\ No newline at end of file
@@ -64,12 +64,13 @@ pub const PPU_DATA_OFFSET: usize = 0x07;
 pub const APU_IO_OAM_DMA_OFFSET: usize = 0x14;
 
 use crate::video::VideoSystem;
+use crate::audio::AudioOutput;
 
 use super::rom::*;
 use super::pad::*;
 
 //This is how we're doing our bus, a big struct that holds all relevant info
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct System {
     //Memory for each component
     pub wram : [u8; WRAM_SIZE],
@@ -77,6 +78,7 @@ pub struct System {
     pub io_reg: [u8; APU_IO_REG_SIZE],
     pub rom : Rom,
     pub video: VideoSystem,
+    pub audio: AudioOutput,
     //Pads
     pub pad1: Pad,
     pub pad2: Pad,
@@ -107,6 +109,7 @@ impl System{
             pad1: Pad::default(),
             pad2: Pad::default(),
             video: VideoSystem::default(),
+            audio: AudioOutput::default(),
             write_oam_data: false,
             write_ppu_scroll:false,
             write_ppu_addr:false,
@@ -126,6 +129,7 @@ impl System {
 
     pub fn reset(&mut self){
         self.video.reset();
+        self.audio.reset();
         self.pad1.reset();
         self.pad2.reset();
         self.wram = [0; WRAM_SIZE];
@@ -144,6 +148,11 @@ impl System {
         self.ppu_scroll_y = 0;
         self.ppu_addr_lower = 0;
     }
+    //Everything on the bus that counts CPU cycles gets ticked here, the CPU calls it once per cycle
+    pub fn step_cpu_cycle(&mut self) {
+        self.rom.step_cpu_cycle();
+        self.audio.push_level(self.rom.audio_output());
+    }
     pub fn write_ppu_vblank(&mut self, is_set : bool){
         if is_set {
             self.ppu_reg[PPU_STATUS_OFFSET] = self.ppu_reg[PPU_STATUS_OFFSET] | 0x80u8;
@@ -11,7 +11,7 @@ pub const PALETTE_TABLE_BASE_ADDR: u16 = 0x3f00;
 pub const VIDEO_ADDRESS_SIZE: u16 = 0x4000;
 
 pub const NAME_TABLE_SIZE: usize = 0x0400;
-pub const NUM_OF_NAME_TABLE: usize = 2;
+pub const NUM_OF_NAME_TABLE: usize = 4;
 pub const ATTRIBUTE_TABLE_SIZE: u16 = 0x0040;
 pub const ATTRIBUTE_TABLE_OFFSET: u16 = 0x03c0; 
 
@@ -50,10 +50,15 @@ macro_rules! arr_write {
         $arr[$index] = $data
     };
 }
+//The 2k of nametable RAM inside the console (CIRAM) is pages 0 and 1. Cartridges decide how it gets laid out, and a few bring their own on top.
+//Pages 2 and 3 are the extra 2k four screen boards carry. It's really on the cartridge, but keeping it here lets every board's plain
+//nametable path reach it through convert_name_table_addr
+pub type NameTables = [[u8; NAME_TABLE_SIZE]; NUM_OF_NAME_TABLE];
+
 #[derive(Clone, Debug)]
 pub struct VideoSystem {
 
-    pub nametables: [[u8; NAME_TABLE_SIZE]; NUM_OF_NAME_TABLE],
+    pub nametables: NameTables,
 
 
     pub palette: [u8; PALETTE_SIZE],
@@ -75,61 +80,62 @@ impl VideoSystem {
     }
 }
 
-impl VideoSystem {
-
-    fn convert_name_table_addr(&self, mirror_mode: MirrorTable, addr: u16) -> (usize, usize) {
-        debug_assert!(addr >= NAME_TABLE_BASE_ADDR);
-        debug_assert!(addr < NAME_TABLE_MIRROR_BASE_ADDR);
-
-        let offset = usize::from(addr - NAME_TABLE_BASE_ADDR) % NAME_TABLE_SIZE;
-        let table_index = match mirror_mode {
-            MirrorTable::Horizontal => {
-                // [A, A]
-                // [B, B]
-                if addr < 0x2800 {
-                    0
-                } else {
-                    1
-                }
-            }
-            MirrorTable::Vertical => {
-                // [A, B]
-                // [A, B]
-                let tmp_addr = if addr >= 0x2800 { addr - 0x800 } else { addr }; 
-                if tmp_addr < 0x2400 {
-                    0
-                } else {
-                    1
-                }
-            }
-            MirrorTable::SingleScreen => {
-                // [A, A]
-                // [A, A]
+//Works out which nametable page (and where in it) an address lands in for a given mirror table
+pub fn convert_name_table_addr(mirror_mode: MirrorTable, addr: u16) -> (usize, usize) {
+    debug_assert!(addr >= NAME_TABLE_BASE_ADDR);
+    debug_assert!(addr < NAME_TABLE_MIRROR_BASE_ADDR);
+
+    let offset = usize::from(addr - NAME_TABLE_BASE_ADDR) % NAME_TABLE_SIZE;
+    let table_index = match mirror_mode {
+        //Nothing loaded yet, the header's H/V bit being clear means horizontal
+        MirrorTable::Horizontal | MirrorTable::Unknown => {
+            // [A, A]
+            // [B, B]
+            if addr < 0x2800 {
                 0
+            } else {
+                1
             }
-            MirrorTable::FourScreen => {
-                // [A, B]
-                // [C, D]
-                usize::from((addr - 0x2000) / 4)
-            }
-            _ => {
-                unimplemented!();
+        }
+        MirrorTable::Vertical => {
+            // [A, B]
+            // [A, B]
+            let tmp_addr = if addr >= 0x2800 { addr - 0x800 } else { addr }; 
+            if tmp_addr < 0x2400 {
+                0
+            } else {
+                1
             }
-        };
-        (table_index, offset)
-    }
+        }
+        MirrorTable::SingleScreenLower => {
+            // [A, A]
+            // [A, A]
+            0
+        }
+        MirrorTable::SingleScreenUpper => {
+            // [B, B]
+            // [B, B]
+            1
+        }
+        MirrorTable::FourScreen => {
+            // [A, B]
+            // [C, D]
+            usize::from((addr - NAME_TABLE_BASE_ADDR) >> 10) & 0x03
+        }
+    };
+    (table_index, offset)
+}
+
+impl VideoSystem {
     pub fn read_u8(&self, rom: &mut Rom, addr: u16) -> u8 {
         debug_assert!(addr < VIDEO_ADDRESS_SIZE);
 
         if addr < NAME_TABLE_BASE_ADDR {
             rom.read_video_u8(addr)
         } else if addr < NAME_TABLE_MIRROR_BASE_ADDR {
-            let (index, offset) = self.convert_name_table_addr(rom.mirror_table, addr);
-            self.nametables[index][offset]
+            rom.read_nametable_u8(&self.nametables, addr)
         } else if addr < PALETTE_TABLE_BASE_ADDR {
-            let (index, offset) =
-                self.convert_name_table_addr(rom.mirror_table, addr - 0x1000);
-            self.nametables[index][offset]
+            rom.read_nametable_u8(&self.nametables, addr - 0x1000)
         } else {
             let index = usize::from(addr - PALETTE_TABLE_BASE_ADDR) % PALETTE_SIZE;
             match index {
@@ -147,13 +153,9 @@ impl VideoSystem {
         if addr < NAME_TABLE_BASE_ADDR {
             rom.write_video_u8(addr, data);
         } else if addr < NAME_TABLE_MIRROR_BASE_ADDR {
-            let (index, offset) = self.convert_name_table_addr(rom.mirror_table, addr);
-            self.nametables[index][offset] = data;
+            rom.write_nametable_u8(&mut self.nametables, addr, data);
         } else if addr < PALETTE_TABLE_BASE_ADDR {
-           
-            let (index, offset) =
-                self.convert_name_table_addr(rom.mirror_table, addr - 0x1000);
-            self.nametables[index][offset] = data;
+            rom.write_nametable_u8(&mut self.nametables, addr - 0x1000, data);
         } else {
             let index = usize::from(addr - PALETTE_TABLE_BASE_ADDR) % PALETTE_SIZE;
           